@@ -1,40 +1,138 @@
 use tiny_fn::tiny_fn;
 use crate::handle::Handle;
-use crate::hook::{Hooks, HookFn};
+use crate::error::Panic;
+use crate::hook::{Hooks, HookFn, PanicFn};
 use std::io;
 use std::sync::Arc;
-use std::thread;
-use crate::core::Core;
-use crate::worker::Worker;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use crate::core::{Config, Core};
 
 tiny_fn! {
     pub(crate) struct NameFn = Fn() -> String;
 }
 
+/// What spawning a task does when the queue of the pool is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Blocks the current thread until there is room for the task.
+    #[default]
+    Block,
+    /// Panics, [`try_spawn`] can be used to handle the error instead.
+    ///
+    /// [`try_spawn`]: crate::handle::Handle::try_spawn
+    Fail
+}
+
+/// What the pool does when a task panics, after calling the [`on_panic`] hook.
+///
+/// Panics of scoped tasks and [`join`] closures are not handled by the policy, as they are
+/// propagated to the caller instead.
+///
+/// [`on_panic`]: WorkerPoolBuilder::on_panic
+/// [`join`]: crate::handle::Handle::join
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// The panic is caught, the handle of the task resolves with [`Panicked`] and the pool keeps
+    /// running.
+    ///
+    /// [`Panicked`]: crate::error::Error::Panicked
+    #[default]
+    Catch,
+    /// The whole process is aborted.
+    Abort,
+    /// The pool is shut down, aborting the queued tasks.
+    Shutdown
+}
+
 /// A builder used to create a new worker pool.
 ///
 /// The pool uses by default the double of threads physical cores the CPU has.
 pub struct WorkerPoolBuilder {
-    threads: usize,
+    min_threads: usize,
+    max_threads: usize,
+    keep_alive: Duration,
+    capacity: Option<usize>,
+    backpressure: Backpressure,
+    panic_policy: PanicPolicy,
     stack_size: Option<usize>,
     name: NameFn<'static>,
     hooks: Hooks
 }
 
+impl Default for WorkerPoolBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl WorkerPoolBuilder {
     /// Creates a new builder.
     pub fn new() -> Self {
         Self {
-            threads: num_cpus::get_physical() * 2,
+            min_threads: num_cpus::get_physical() * 2,
+            max_threads: num_cpus::get_physical() * 2,
+            keep_alive: Duration::from_secs(10),
+            capacity: None,
+            backpressure: Backpressure::default(),
+            panic_policy: PanicPolicy::default(),
             stack_size: None,
             name: NameFn::new(|| String::from("Worker-Pool worker")),
             hooks: Hooks::default()
         }
     }
 
-    /// Sets the number of threads to use.
+    /// Sets the number of threads to use, the pool will have a fixed number of threads.
     pub fn threads(&mut self, threads: usize) -> &mut Self {
-        self.threads = threads;
+        self.min_threads = threads;
+        self.max_threads = threads;
+        self
+    }
+
+    /// Sets the minimum number of threads, the pool starts with this amount of threads and
+    /// idle threads won't retire below it.
+    pub fn min_threads(&mut self, threads: usize) -> &mut Self {
+        self.min_threads = threads;
+        self
+    }
+
+    /// Sets the maximum number of threads, the pool spawns more threads up to this amount
+    /// when all of them are busy.
+    pub fn max_threads(&mut self, threads: usize) -> &mut Self {
+        self.max_threads = threads;
+        self
+    }
+
+    /// Sets how long a thread can be idle before retiring when the pool has more threads than
+    /// the minimum. Defaults to 10 seconds.
+    pub fn keep_alive(&mut self, keep_alive: Duration) -> &mut Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Sets the maximum number of tasks that can be queued, by default the queue is unbounded.
+    ///
+    /// When the queue is full, spawning a task behaves as set by [`backpressure`].
+    ///
+    /// [`backpressure`]: WorkerPoolBuilder::backpressure
+    pub fn queue_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Sets what spawning a task does when the queue is full, defaults to [`Block`].
+    ///
+    /// [`Block`]: Backpressure::Block
+    pub fn backpressure(&mut self, backpressure: Backpressure) -> &mut Self {
+        self.backpressure = backpressure;
+        self
+    }
+
+    /// Sets what the pool does when a task panics, defaults to [`Catch`].
+    ///
+    /// [`Catch`]: PanicPolicy::Catch
+    pub fn panic_policy(&mut self, policy: PanicPolicy) -> &mut Self {
+        self.panic_policy = policy;
         self
     }
 
@@ -47,9 +145,12 @@ impl WorkerPoolBuilder {
     }
 
     /// Sets a function used to determine the name of the worker threads.
+    ///
+    /// The function can be called from several threads at the same time, as threads are spawned
+    /// on demand by the ones spawning tasks.
     pub fn set_name_fn<F>(&mut self, fun: F) -> &mut Self
     where
-        F: Fn() -> String + Send + 'static
+        F: Fn() -> String + Send + Sync + 'static
     {
         self.name = NameFn::new(fun);
         self
@@ -65,6 +166,8 @@ impl WorkerPoolBuilder {
     }
 
     /// Sets a function to execute before stopping each thread.
+    ///
+    /// It isn't called for threads that die because a hook panicked.
     pub fn on_stop<F>(&mut self, fun: F) -> &mut Self
     where
         F: Fn() + Send + 'static
@@ -91,23 +194,55 @@ impl WorkerPoolBuilder {
         self
     }
 
+    /// Sets a function to execute when a task panics, before the [`panic policy`] is applied.
+    ///
+    /// Tasks can panic in several worker threads at once, so the function may be called
+    /// concurrently.
+    ///
+    /// [`panic policy`]: WorkerPoolBuilder::panic_policy
+    pub fn on_panic<F>(&mut self, fun: F) -> &mut Self
+    where
+        F: Fn(&Panic) + Send + Sync + 'static
+    {
+        self.hooks.on_panic = Some(PanicFn::new(fun));
+        self
+    }
+
+    /// Sets a function to execute when a worker thread dies, receiving the panic that killed it.
+    ///
+    /// Dead threads are replaced by new ones, this can only happen if a hook panics, as the
+    /// panics of tasks are caught. The function is called by the dying thread, so it may run in
+    /// several threads at once, and the [`on_stop`] hook isn't called for it.
+    ///
+    /// Threads that die before executing any task are replaced after a delay, starting at 10ms
+    /// and doubled every time it happens again in a row, up to a second.
+    ///
+    /// [`on_stop`]: WorkerPoolBuilder::on_stop
+    pub fn on_worker_restart<F>(&mut self, fun: F) -> &mut Self
+    where
+        F: Fn(&Panic) + Send + Sync + 'static
+    {
+        self.hooks.on_restart = Some(PanicFn::new(fun));
+        self
+    }
+
     /// Builds and starts the pool consuming the builder.
     pub fn build_owned(self) -> io::Result<Handle> {
-        let mut handles = Vec::new();
-        let core = Arc::new(Core::new(self.hooks));
-
-        for _ in 0..self.threads {
-            let mut builder = thread::Builder::new()
-                .name(self.name.call());
-            if let Some(size) = self.stack_size {
-                builder = builder.stack_size(size);
-            }
-
-            let core = Arc::clone(&core);
-            handles.push(builder.spawn(|| Worker::new(core).run())?);
-        }
+        let config = Config {
+            name: self.name,
+            stack_size: self.stack_size,
+            keep_alive: self.keep_alive,
+            capacity: self.capacity,
+            backpressure: self.backpressure,
+            panic_policy: self.panic_policy
+        };
+        let max_threads = self.max_threads.max(self.min_threads);
+        let core = Arc::new(Core::new(self.hooks, config, self.min_threads, max_threads));
 
-        *core.handles.lock() = handles;
+        core.threads.store(self.min_threads, Ordering::Release);
+        for _ in 0..self.min_threads {
+            core.spawn_worker()?;
+        }
 
         crate::context::set(Handle { core: Arc::clone(&core) });
 
@@ -116,7 +251,7 @@ impl WorkerPoolBuilder {
 
     /// Builds and starts the pool without taking ownership of the builder
     pub fn build(&mut self) -> io::Result<Handle> {
-        let this = std::mem::replace(self, Self::new());
+        let this = std::mem::take(self);
         this.build_owned()
     }
 
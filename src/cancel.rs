@@ -0,0 +1,115 @@
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+const QUEUED: u8 = 0;
+const RUNNING: u8 = 1;
+const CANCELLED: u8 = 2;
+const FINISHED: u8 = 3;
+const ABORTED: u8 = 4;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static CURRENT: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+}
+
+/// A token shared between a task and its [`JoinHandle`], used by the running task to know if
+/// it has been asked to stop.
+///
+/// When a task is cancelled via [`abort`] while it is running, the token is marked as cancelled,
+/// and the output of the task is replaced by [`Cancelled`] once it returns, so long running tasks
+/// should poll [`is_cancelled`] and return early.
+///
+/// [`JoinHandle`]: crate::join::JoinHandle
+/// [`abort`]: crate::join::JoinHandle::abort
+/// [`Cancelled`]: crate::error::Error::Cancelled
+/// [`is_cancelled`]: CancellationToken::is_cancelled
+#[derive(Clone)]
+pub struct CancellationToken {
+    state: Arc<AtomicU8>,
+    id: TaskId
+}
+
+/// An identifier of a task spawned with a handle, unique among all the pools.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+impl CancellationToken {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: Arc::new(AtomicU8::new(QUEUED)),
+            id: TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+        }
+    }
+
+    /// Returns the id of the task owning this token.
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Gets the token of the task being executed by the current thread, returning [`None`]
+    /// if called outside of a task or inside a task spawned using [`spawn_detached`].
+    ///
+    /// [`None`]: std::option::Option::None
+    /// [`spawn_detached`]: crate::spawn_detached
+    pub fn current() -> Option<Self> {
+        CURRENT.with(|cell| cell.borrow().clone())
+    }
+
+    /// Returns whether the task owning this token has been asked to stop.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.load(Ordering::Acquire) == CANCELLED
+    }
+
+    /// Marks the task as running, returning `false` if it was aborted before.
+    pub(crate) fn start(&self) -> bool {
+        self.transition(QUEUED, RUNNING)
+    }
+
+    /// Marks the task as finished, returning `true` if it was cancelled while running.
+    pub(crate) fn finish(&self) -> bool {
+        self.state.swap(FINISHED, Ordering::AcqRel) == CANCELLED
+    }
+
+    /// Returns whether the task was aborted before it started running.
+    pub(crate) fn is_aborted(&self) -> bool {
+        self.state.load(Ordering::Acquire) == ABORTED
+    }
+
+    /// Aborts the task, returning `true` if it was still queued and so it won't be executed.
+    pub(crate) fn abort(&self) -> bool {
+        self.transition(QUEUED, ABORTED)
+    }
+
+    /// Asks the task to stop, returning `true` if it was running.
+    pub(crate) fn cancel(&self) -> bool {
+        self.transition(RUNNING, CANCELLED)
+    }
+
+    /// Sets the token as the one of the current thread until the returned guard is dropped.
+    pub(crate) fn enter(&self) -> TokenGuard {
+        let previous = CURRENT.with(|cell| cell.replace(Some(self.clone())));
+        TokenGuard(previous)
+    }
+
+    fn transition(&self, from: u8, to: u8) -> bool {
+        self.state.compare_exchange(from, to, Ordering::AcqRel, Ordering::Acquire).is_ok()
+    }
+}
+
+pub(crate) struct TokenGuard(Option<CancellationToken>);
+
+impl Drop for TokenGuard {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        let _ = CURRENT.try_with(|cell| *cell.borrow_mut() = previous);
+    }
+}
@@ -2,11 +2,11 @@ use std::cell::RefCell;
 use crate::handle::Handle;
 
 thread_local! {
-    static HANDLE: RefCell<Option<Handle>> = RefCell::new(None);
+    static HANDLE: RefCell<Option<Handle>> = const { RefCell::new(None) };
 }
 
 pub fn get() -> Handle {
-    try_get().unwrap()
+    try_get().expect("Not inside the context of a worker pool")
 }
 
 pub fn try_get() -> Option<Handle> {
@@ -1,78 +1,615 @@
-use std::thread::JoinHandle;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU8, AtomicUsize, Ordering};
+use std::task::{Context, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::builder::{Backpressure, NameFn, PanicPolicy};
 use crate::driver::{Driver, Either};
+use crate::error::{Error, Panic, SpawnError};
+use crate::future::Futures;
+use crate::handle::State;
 use crate::hook::Hooks;
+use crate::metrics::{Counters, Metrics};
+use crate::park::Sleepers;
 use crate::timer::Timer;
 use parking_lot::{Condvar, Mutex};
 use crate::periodic::PeriodicTask;
+use crate::signal::Blocker;
+use crate::priority::Priority;
 use crate::sync::Task;
+use crate::worker::Worker;
 
-/// The core shared among all worker threads and handles.
+/// The pool accepts and executes tasks.
+const RUNNING: u8 = 0;
+/// The pool doesn't accept new tasks but executes the queued ones.
+const DRAINING: u8 = 1;
+/// The pool has stopped.
+const STOPPED: u8 = 2;
+
+/// How long a worker thread that died before executing any task waits before being replaced,
+/// doubled for every thread in a row that does so.
+const RESTART_BACKOFF: Duration = Duration::from_millis(10);
+/// The longest a dead worker thread waits before being replaced.
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The configuration of the pool.
+pub struct Config {
+    /// The function used to name the threads.
+    pub name: NameFn<'static>,
+    /// The stack size of the threads.
+    pub stack_size: Option<usize>,
+    /// How long a thread can be idle before retiring if there are more than the minimum.
+    pub keep_alive: Duration,
+    /// The maximum number of queued tasks.
+    pub capacity: Option<usize>,
+    /// What spawning does when the queue is full.
+    pub backpressure: Backpressure,
+    /// What the pool does when a task panics.
+    pub panic_policy: PanicPolicy
+}
+
+/// The threads and futures waiting for the queue to have room.
 #[derive(Default)]
+pub struct Space {
+    /// The number of threads and futures waiting.
+    waiting: AtomicUsize,
+    wakers: Mutex<Vec<Waker>>,
+    condvar: Condvar
+}
+
+/// The core shared among all worker threads and handles.
 pub struct Core {
     /// The queue of tasks of the pool.
     pub driver: Driver,
     /// The hooks the pool has.
     pub hooks: Hooks,
-    /// The timer used to store periodic tasks that are not ready to run.
+    /// The configuration of the worker threads.
+    pub config: Config,
+    /// The timer used to store periodic and delayed tasks that are not ready to run.
     pub timer: Mutex<Timer>,
-    /// A mutex used along with the condvar to put to sleep the threads.
+    /// A mutex used along with the condvar to wait for the threads to exit.
     pub mutex: Mutex<()>,
-    /// The condvar used along the mutex to put to sleep the threads.
+    /// The condvar notified every time a thread exits.
     pub condvar: Condvar,
+    /// The worker threads sleeping waiting for tasks.
+    pub sleepers: Sleepers,
+    /// Incremented every time the earliest deadline of the timer changes.
+    pub timer_epoch: AtomicUsize,
+    /// The threads and futures waiting for the queue to have room.
+    pub space: Space,
+    /// The futures spawned into the pool that haven't completed yet.
+    pub futures: Futures,
     /// The handles of the worker threads
     pub handles: Mutex<Vec<JoinHandle<()>>>,
-    /// Whether the pool should exit or not.
-    pub exit: bool
+    /// The number of worker threads alive.
+    pub threads: AtomicUsize,
+    /// The number of worker threads in a row that died before executing any task.
+    early_deaths: AtomicU32,
+    /// The minimum number of worker threads, idle threads won't retire below this count.
+    pub min_threads: AtomicUsize,
+    /// The maximum number of worker threads, the pool won't grow beyond this count.
+    pub max_threads: AtomicUsize,
+    /// The state of the pool, whether it is running, draining or stopped.
+    pub state: AtomicU8,
+    /// The error the tasks left at the queue are aborted with once the pool stops.
+    stop_error: Mutex<fn() -> Error>,
+    /// The counters used to build the metrics of the pool.
+    pub counters: Counters
 }
 
 impl Core {
-    pub fn new(hooks: Hooks) -> Self {
+    pub fn new(hooks: Hooks, config: Config, min_threads: usize, max_threads: usize) -> Self {
         Self {
+            driver: Driver::new(config.capacity),
             hooks,
-            ..Default::default()
+            config,
+            timer: Mutex::default(),
+            mutex: Mutex::default(),
+            condvar: Condvar::new(),
+            sleepers: Sleepers::default(),
+            timer_epoch: AtomicUsize::new(0),
+            space: Space::default(),
+            futures: Futures::default(),
+            handles: Mutex::default(),
+            threads: AtomicUsize::new(0),
+            early_deaths: AtomicU32::new(0),
+            min_threads: AtomicUsize::new(min_threads),
+            max_threads: AtomicUsize::new(max_threads),
+            state: AtomicU8::new(RUNNING),
+            stop_error: Mutex::new(|| Error::PoolShutdown),
+            counters: Counters::default()
+        }
+    }
+
+    /// Returns whether the worker threads should keep running.
+    pub fn is_running(&self) -> bool {
+        self.state.load(Ordering::Acquire) != STOPPED
+    }
+
+    /// Returns whether the pool is being shut down gracefully.
+    pub fn is_draining(&self) -> bool {
+        self.state.load(Ordering::Acquire) == DRAINING
+    }
+
+    /// Returns the lifecycle state of the pool.
+    pub fn state(&self) -> State {
+        match self.state.load(Ordering::Acquire) {
+            RUNNING => State::Running,
+            DRAINING => State::Draining,
+            _ => State::Stopped
+        }
+    }
+
+    /// Returns whether tasks can be spawned from the current thread.
+    fn accepts_tasks(&self) -> bool {
+        match self.state.load(Ordering::Acquire) {
+            RUNNING => true,
+            // Tasks being drained can still spawn other tasks, so they can finish.
+            DRAINING => self.driver.is_worker(),
+            _ => false
         }
     }
 
     fn assert_running(&self) {
-        if self.exit {
+        if !self.accepts_tasks() {
             panic!("Threadpool not running");
         }
     }
 
-    pub fn schedule(&self, task: Task) {
+    /// Like [`reserve`], but never blocks nor panics, returning why the slot couldn't be
+    /// reserved instead.
+    ///
+    /// [`reserve`]: Core::reserve
+    pub fn try_reserve(&self) -> Result<(), SpawnError<()>> {
+        if !self.accepts_tasks() {
+            Err(SpawnError::Shutdown(()))
+        } else if !self.driver.reserve() {
+            Err(SpawnError::QueueFull(()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reserves a slot at the queue for a task, returning whether it could be reserved.
+    ///
+    /// If the queue is full and `block` is true, the current thread is blocked until there is
+    /// room for the task, worker threads execute queued tasks meanwhile.
+    pub fn reserve(&self, block: bool) -> bool {
         self.assert_running();
-        self.driver.schedule(Either::Left(task));
-        self.condvar.notify_one();
+        if self.driver.reserve() {
+            return true;
+        }
+
+        if !block {
+            return false;
+        }
+
+        // The current thread may be the only one able to make room, so it executes queued
+        // tasks while it waits.
+        if self.driver.is_worker() {
+            let blocker = Blocker::new();
+            let waker = Waker::from(Arc::clone(blocker.signal()));
+            let cx = Context::from_waker(&waker);
+            while !self.poll_reserve(&cx) {
+                blocker.wait();
+            }
+            return true;
+        }
+
+        let mut lock = self.space.wakers.lock();
+        self.space.waiting.fetch_add(1, Ordering::SeqCst);
+
+        while self.is_running() && !self.driver.reserve() {
+            self.space.condvar.wait(&mut lock);
+        }
+
+        self.space.waiting.fetch_sub(1, Ordering::SeqCst);
+        drop(lock);
+
+        // The pool may have been stopped while waiting.
+        self.assert_running();
+        true
     }
 
-    pub fn schedule_periodical(&self, task: PeriodicTask) {
+    /// Like [`reserve`], but registers the waker of the given context to be woken when there
+    /// is room, instead of blocking.
+    ///
+    /// [`reserve`]: Core::reserve
+    pub fn poll_reserve(&self, cx: &Context<'_>) -> bool {
         self.assert_running();
-        let notify = task.can_run();
-        self.timer.lock().schedule(task);
-        if notify {
-            self.condvar.notify_one();
+        if self.driver.reserve() {
+            return true;
+        }
+
+        let mut lock = self.space.wakers.lock();
+        self.space.waiting.fetch_add(1, Ordering::SeqCst);
+
+        // Try again after registering, a slot may have been freed in between.
+        if self.driver.reserve() {
+            self.space.waiting.fetch_sub(1, Ordering::SeqCst);
+            return true;
         }
+
+        lock.push(cx.waker().clone());
+        false
     }
 
-    pub fn shutdown(&self) {
+    /// Pops a task from the queue, notifying the ones waiting for room.
+    pub fn pop(&self) -> Option<Either<Task, PeriodicTask>> {
+        let task = self.driver.pop();
+        if task.is_some() {
+            self.notify_space();
+        }
+        task
+    }
+
+    fn notify_space(&self) {
+        if self.space.waiting.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+
+        let mut lock = self.space.wakers.lock();
+        let wakers = std::mem::take(&mut *lock);
+        self.space.waiting.fetch_sub(wakers.len(), Ordering::SeqCst);
+        if self.is_running() {
+            self.space.condvar.notify_one();
+        } else {
+            self.space.condvar.notify_all();
+        }
+        drop(lock);
+
+        wakers.into_iter().for_each(Waker::wake);
+    }
+
+    /// Schedules a task, a slot must have been reserved for it using [`reserve`].
+    ///
+    /// [`reserve`]: Core::reserve
+    ///
+    /// The task is aborted if the pool stopped accepting tasks after the slot was reserved.
+    pub fn schedule(self: &Arc<Self>, task: Task, priority: Priority) {
+        if !self.accepts_tasks() {
+            task.abort(Error::PoolShutdown);
+            self.counters.aborted(1);
+            return;
+        }
+
+        self.driver.schedule_reserved(Either::Left(task), priority);
+        self.counters.spawned();
+        self.notify_worker();
+    }
+
+    /// Like [`schedule`], but schedules a batch of tasks at once, waking up at most as many
+    /// threads as tasks, a slot must have been reserved for each of them.
+    ///
+    /// [`schedule`]: Core::schedule
+    pub fn schedule_batch(self: &Arc<Self>, tasks: Vec<Task>, priority: Priority) {
+        if !self.accepts_tasks() {
+            self.counters.aborted(tasks.len());
+            tasks.into_iter().for_each(|task| task.abort(Error::PoolShutdown));
+            return;
+        }
+
+        let count = tasks.len();
+        self.driver.schedule_batch_reserved(tasks.into_iter().map(Either::Left).collect(), priority);
+        self.counters.spawned_many(count);
+
+        let mut woken = self.sleepers.notify(count);
+        while woken < count && matches!(self.grow(), Ok(true)) {
+            woken += 1;
+        }
+    }
+
+    /// Schedules a future woken up again, it already went through the queue once, so it doesn't
+    /// need a slot nor counts as a new task.
+    ///
+    /// The task is aborted if the pool isn't running anymore.
+    pub fn reschedule(self: &Arc<Self>, task: Task) {
+        if !self.is_running() {
+            task.abort(Error::PoolShutdown);
+            return;
+        }
+
+        self.driver.schedule(Either::Left(task), Priority::Normal);
+        self.notify_worker();
+    }
+
+    /// Wakes up a worker thread to execute a task just scheduled.
+    fn notify_worker(self: &Arc<Self>) {
+        // If every thread is busy the task would have to wait, so spawn a new one if possible,
+        // busy threads check the queue again before sleeping, so they pick it up otherwise.
+        if !self.sleepers.notify_one() {
+            let _ = self.grow();
+        }
+    }
+
+    pub fn schedule_periodical(self: &Arc<Self>, task: PeriodicTask) {
+        self.assert_running();
+        let earliest = self.timer.lock().schedule(task);
+        if earliest {
+            self.notify_timer();
+        }
+        self.ensure_timer_thread();
+    }
+
+    /// Schedules a task to be executed once the given instant is reached, returning its id at
+    /// the timer.
+    pub fn schedule_at(self: &Arc<Self>, task: Task, at: Instant) -> u64 {
         self.assert_running();
-        self.driver.clear();
+        let (id, earliest) = self.timer.lock().schedule_delayed(task, at);
+        self.counters.spawned();
+        if earliest {
+            self.notify_timer();
+        }
+        self.ensure_timer_thread();
+        id
+    }
+
+    /// Spawns a thread if there is none alive, as someone must watch the timer.
+    ///
+    /// The task must have been pushed to the timer before calling this, and threads retiring
+    /// check the timer after leaving the count, so one of both sides spawns the thread.
+    pub fn ensure_timer_thread(self: &Arc<Self>) {
+        if self.threads.load(Ordering::SeqCst) == 0 && !self.timer.lock().is_empty() {
+            let _ = self.grow();
+        }
+    }
+
+    /// Notifies the worker threads that the earliest deadline of the timer changed, so the
+    /// thread watching the timer wakes up earlier, or a thread starts watching it.
+    fn notify_timer(&self) {
+        self.timer_epoch.fetch_add(1, Ordering::SeqCst);
+        self.sleepers.notify_timer();
+    }
+
+    /// Schedules again a periodic task after running, the task is dropped if the pool is being
+    /// shut down.
+    pub fn reschedule_periodical(self: &Arc<Self>, task: PeriodicTask) {
+        if self.state.load(Ordering::Acquire) == RUNNING {
+            self.schedule_periodical(task);
+        }
+    }
+
+    /// Spawns a new worker thread if the pool is below its maximum, returning whether a thread
+    /// was spawned.
+    fn grow(self: &Arc<Self>) -> io::Result<bool> {
+        let max = self.max_threads.load(Ordering::Acquire);
+        let reserved = self.threads.fetch_update(Ordering::AcqRel, Ordering::Acquire, |threads| {
+            (threads < max).then_some(threads + 1)
+        });
+
+        if reserved.is_err() || !self.is_running() {
+            return Ok(false);
+        }
+
+        self.spawn_worker().map(|_| true)
+    }
+
+    /// Spawns a new worker thread, the thread must have been already added to the count.
+    pub fn spawn_worker(self: &Arc<Self>) -> io::Result<()> {
+        let mut builder = thread::Builder::new()
+            .name(self.config.name.call());
+        if let Some(size) = self.config.stack_size {
+            builder = builder.stack_size(size);
+        }
+
+        let core = Arc::clone(self);
+        match builder.spawn(|| Worker::new(core).run()) {
+            Ok(handle) => {
+                let mut lock = self.handles.lock();
+                lock.retain(|handle| !handle.is_finished());
+                lock.push(handle);
+                Ok(())
+            },
+            Err(error) => {
+                self.threads.fetch_sub(1, Ordering::AcqRel);
+                Err(error)
+            }
+        }
+    }
+
+    /// Replaces the current worker thread, which died because of the given panic, the dead
+    /// thread must still be in the count, as the new one takes its place.
+    ///
+    /// If the thread died before executing any task, the new one would most likely die the same
+    /// way, like when the start hook panics, so the replacement is delayed more and more.
+    pub fn restart_worker(self: &Arc<Self>, panic: Panic, early: bool) {
+        let current = thread::current().id();
+        self.handles.lock().retain(|handle| handle.thread().id() != current);
+
+        if early {
+            let deaths = self.early_deaths.fetch_add(1, Ordering::AcqRel).min(16);
+            let backoff = RESTART_BACKOFF.saturating_mul(1 << deaths).min(MAX_RESTART_BACKOFF);
+            let until = Instant::now() + backoff;
+            // Sleep in steps, so the pool doesn't wait for us if it stops meanwhile.
+            while self.is_running() && Instant::now() < until {
+                thread::sleep(until.saturating_duration_since(Instant::now()).min(RESTART_BACKOFF));
+            }
+        } else {
+            self.early_deaths.store(0, Ordering::Release);
+        }
+
+        if !self.is_running() {
+            self.threads.fetch_sub(1, Ordering::AcqRel);
+            return;
+        }
+
+        self.counters.restarted();
+        if let Some(fun) = self.hooks.on_restart.as_ref() {
+            fun.call(&panic);
+        }
+        // The count is decremented if the thread can't be spawned.
+        let _ = self.spawn_worker();
+    }
+
+    /// Resizes the pool to the given number of threads, spawning the missing ones and making
+    /// the extra ones retire.
+    pub fn set_threads(self: &Arc<Self>, threads: usize) -> io::Result<()> {
+        self.min_threads.store(threads, Ordering::Release);
+        self.max_threads.store(threads, Ordering::Release);
+
+        while self.grow()? {}
+
+        // Wake up the threads so the extra ones retire.
+        self.sleepers.notify_all();
+        Ok(())
+    }
+
+    /// Tries to retire the current worker thread, which is possible when the pool has more
+    /// threads than the maximum, or when it has been idle for longer than the keep alive time
+    /// and the pool has more threads than the minimum.
+    pub fn try_retire(&self, idle: Duration) -> bool {
+        let min = self.min_threads.load(Ordering::Acquire);
+        let max = self.max_threads.load(Ordering::Acquire);
+        let expired = idle >= self.config.keep_alive;
+        let retire = |threads| threads > max || (expired && threads > min);
+
+        if !retire(self.threads.load(Ordering::Acquire)) {
+            return false;
+        }
+
+        // The last thread stays alive while there are tasks waiting at the timer.
+        let last = if self.timer.lock().is_empty() { 0 } else { 1 };
+        self.threads.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |threads| {
+            (retire(threads) && threads > last).then(|| threads - 1)
+        }).is_ok()
+    }
+
+    /// Shuts down the pool, aborting all the queued tasks.
+    pub fn shutdown(&self) {
+        self.begin_shutdown();
+        self.stop(|| Error::PoolShutdown);
+    }
+
+    /// Shuts down the pool, executing all the queued tasks before stopping.
+    pub fn shutdown_graceful(&self) {
+        self.begin_shutdown();
+        crate::worker::drain(self, None);
+        self.wait_drained(None);
+        self.stop(|| Error::PoolShutdown);
+    }
+
+    /// Like [`shutdown_graceful`], but the queued tasks are aborted if they are not finished
+    /// before the timeout, returning whether all of them could be executed.
+    ///
+    /// [`shutdown_graceful`]: Core::shutdown_graceful
+    pub fn shutdown_timeout(&self, timeout: Duration) -> bool {
+        self.begin_shutdown();
+        let deadline = Instant::now() + timeout;
+        crate::worker::drain(self, Some(deadline));
+        let drained = self.wait_drained(Some(deadline));
+        self.stop(|| Error::TimedOut);
+        drained
+    }
+
+    /// Calls the panic hook of the pool.
+    pub fn report_panic(&self, panic: &Panic) {
+        if let Some(fun) = self.hooks.on_panic.as_ref() {
+            fun.call(panic);
+        }
+    }
+
+    /// Applies the panic policy of the pool, after reporting the panic with [`report_panic`].
+    ///
+    /// [`report_panic`]: Core::report_panic
+    pub fn apply_panic_policy(&self) {
+        match self.config.panic_policy {
+            PanicPolicy::Catch => (),
+            PanicPolicy::Abort => std::process::abort(),
+            // Another task may have already shut down the pool.
+            PanicPolicy::Shutdown => if self.try_begin_shutdown() {
+                self.stop(|| Error::PoolShutdown);
+            }
+        }
+    }
+
+    fn begin_shutdown(&self) {
+        if !self.try_begin_shutdown() {
+            panic!("Threadpool not running");
+        }
+    }
+
+    /// Starts shutting down the pool, returning `false` if it was already shutting down.
+    fn try_begin_shutdown(&self) -> bool {
+        let result = self.state.compare_exchange(
+            RUNNING,
+            DRAINING,
+            Ordering::AcqRel,
+            Ordering::Acquire
+        );
+
+        if result.is_err() {
+            return false;
+        }
+
+        let aborted = self.timer.lock().clear();
+        self.counters.aborted(aborted);
         crate::context::clear();
-        let mut lock = self.handles.lock();
 
-        // SAFETY: The Core struct cannot be accessed directly, to use this method a Handle
-        // instance must be consumed, and since we assert the pool is running before doing anything
-        // there is no possibility of a data race here.
-        unsafe {
-            let this = &mut *(self as *const Self as *mut Self);
-            this.exit = true;
+        self.sleepers.notify_all();
+        true
+    }
+
+    /// Waits until the worker threads run out of tasks and exit, or until the deadline passes.
+    fn wait_drained(&self, deadline: Option<Instant>) -> bool {
+        // A worker calling shutdown won't exit until the task calling it returns.
+        let current = if self.driver.is_worker() { 1 } else { 0 };
+        let mut lock = self.mutex.lock();
+
+        while self.threads.load(Ordering::Acquire) > current {
+            match deadline {
+                Some(deadline) => {
+                    if self.condvar.wait_until(&mut lock, deadline).timed_out() {
+                        return self.threads.load(Ordering::Acquire) <= current;
+                    }
+                },
+                None => self.condvar.wait(&mut lock)
+            }
         }
 
-        self.condvar.notify_all();
+        true
+    }
 
-        lock.drain(..).for_each(|handle| {
+    /// Stops the pool, aborting the queued tasks with the given error and waiting for all threads
+    /// to exit.
+    ///
+    /// If called from a worker thread, the other threads are left to exit on their own, as they
+    /// may be waiting for the task that called this.
+    fn stop(&self, error: fn() -> Error) {
+        *self.stop_error.lock() = error;
+        self.state.store(STOPPED, Ordering::Release);
+        self.counters.aborted(self.driver.clear(error));
+        // Idle futures are only kept alive by their wakers, so they would never resolve.
+        self.counters.aborted(self.futures.abort_all(error));
+        // Wake up everyone waiting for room, so they see the pool stopped.
+        self.notify_space();
+        self.sleepers.notify_all();
+
+        // The current thread exits once the current task returns, and each worker aborts the
+        // tasks left once it exits.
+        if self.driver.is_worker() {
+            return;
+        }
+
+        let handles = std::mem::take(&mut *self.handles.lock());
+        handles.into_iter().for_each(|handle| {
             let _ = handle.join();
         });
+
+        self.abort_left();
+    }
+
+    /// Aborts the tasks left at the queue once the pool stopped, like the ones workers move from
+    /// their local queues to the global ones when exiting.
+    pub fn abort_left(&self) {
+        let error = *self.stop_error.lock();
+        self.counters.aborted(self.driver.clear(error));
+    }
+
+    /// Takes a snapshot of the metrics of the pool.
+    pub fn metrics(&self) -> Metrics {
+        let pending_timers = self.timer.lock().len();
+        self.counters.snapshot(self.driver.len(), pending_timers)
     }
 }
 
@@ -0,0 +1,288 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: i64 = 86400;
+/// How far in the future to look for the next fire time before giving up, leap days can take up
+/// to 8 years to happen again on a given weekday.
+const SEARCH_LIMIT: i64 = 10 * 366 * SECONDS_PER_DAY;
+
+const MONTHS: &[&str] = &[
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC"
+];
+const WEEKDAYS: &[&str] = &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// The description of a field of a cron expression.
+struct Field {
+    name: &'static str,
+    min: u32,
+    max: u32,
+    names: &'static [&'static str]
+}
+
+const SECOND: Field = Field { name: "second", min: 0, max: 59, names: &[] };
+const MINUTE: Field = Field { name: "minute", min: 0, max: 59, names: &[] };
+const HOUR: Field = Field { name: "hour", min: 0, max: 23, names: &[] };
+const DAY: Field = Field { name: "day of month", min: 1, max: 31, names: &[] };
+const MONTH: Field = Field { name: "month", min: 1, max: 12, names: MONTHS };
+// Both 0 and 7 are sunday.
+const WEEKDAY: Field = Field { name: "day of week", min: 0, max: 7, names: WEEKDAYS };
+
+/// The error returned when a cron expression is not valid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CronError {
+    /// The expression doesn't have 5 or 6 fields, contains the number of fields found.
+    FieldCount(usize),
+    /// A field contains a value that can't be parsed.
+    InvalidValue {
+        /// The name of the field.
+        field: &'static str,
+        /// The value that couldn't be parsed.
+        value: String
+    },
+    /// A field contains a value outside of its range.
+    OutOfRange {
+        /// The name of the field.
+        field: &'static str,
+        /// The value outside of the range.
+        value: u32
+    },
+    /// The expression is valid but it never matches any date, like the 30th of February.
+    Unsatisfiable
+}
+
+impl fmt::Display for CronError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FieldCount(count) => write!(f, "expected 5 or 6 fields, found {}", count),
+            Self::InvalidValue { field, value } => write!(f, "invalid {} value: {:?}", field, value),
+            Self::OutOfRange { field, value } => write!(f, "{} value out of range: {}", field, value),
+            Self::Unsatisfiable => f.write_str("the expression never matches any date")
+        }
+    }
+}
+
+impl Error for CronError {}
+
+/// A parsed cron expression, used to compute the times a cron task fires.
+///
+/// Both the standard 5 field syntax (`minute hour day-of-month month day-of-week`) and the
+/// 6 field syntax with a leading seconds field are supported. Each field accepts `*`, single
+/// values, ranges (`1-5`), steps (`*/15`, `0-30/5`, `10/5`) and comma separated lists of them.
+/// Months and days of week also accept their three letter english names (`JAN`, `MON`...).
+///
+/// As in most cron implementations, if both the day of month and the day of week are
+/// restricted, the expression matches when any of them matches.
+///
+/// Times are computed in UTC.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    seconds: u64,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of month field is `*`.
+    any_day: bool,
+    /// Whether the day of week field is `*`.
+    any_weekday: bool
+}
+
+impl Schedule {
+    /// Parses a cron expression.
+    pub fn parse(expr: &str) -> Result<Self, CronError> {
+        let fields = expr.split_whitespace().collect::<Vec<_>>();
+        let (seconds, rest) = match fields.len() {
+            5 => (1, &fields[..]),
+            6 => (parse_field(&SECOND, fields[0])?, &fields[1..]),
+            count => return Err(CronError::FieldCount(count))
+        };
+
+        let mut weekdays = parse_field(&WEEKDAY, rest[4])?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+
+        Ok(Self {
+            seconds,
+            minutes: parse_field(&MINUTE, rest[0])?,
+            hours: parse_field(&HOUR, rest[1])?,
+            days: parse_field(&DAY, rest[2])?,
+            months: parse_field(&MONTH, rest[3])?,
+            weekdays,
+            any_day: rest[2].starts_with('*'),
+            any_weekday: rest[4].starts_with('*')
+        })
+    }
+
+    /// Returns the first time after the given one that matches the expression, or [`None`] if
+    /// the expression never matches.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let start = time.duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs() as i64)
+            .unwrap_or(0) + 1;
+        let mut current = start;
+
+        while current - start <= SEARCH_LIMIT {
+            let days = current.div_euclid(SECONDS_PER_DAY);
+            let seconds = current.rem_euclid(SECONDS_PER_DAY);
+            let (year, month, day) = civil_from_days(days);
+
+            if !matches(self.months, month) {
+                let (year, month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+                current = days_from_civil(year, month, 1) * SECONDS_PER_DAY;
+                continue;
+            }
+
+            if !self.matches_day(day, weekday(days)) {
+                current = (days + 1) * SECONDS_PER_DAY;
+                continue;
+            }
+
+            let hour = seconds / 3600;
+            if !matches(self.hours, hour as u32) {
+                current = days * SECONDS_PER_DAY + (hour + 1) * 3600;
+                continue;
+            }
+
+            let minute = seconds % 3600 / 60;
+            if !matches(self.minutes, minute as u32) {
+                current = days * SECONDS_PER_DAY + hour * 3600 + (minute + 1) * 60;
+                continue;
+            }
+
+            if !matches(self.seconds, (seconds % 60) as u32) {
+                current += 1;
+                continue;
+            }
+
+            return Some(UNIX_EPOCH + Duration::from_secs(current as u64));
+        }
+
+        None
+    }
+
+    fn matches_day(&self, day: u32, weekday: u32) -> bool {
+        let day = matches(self.days, day);
+        let weekday = matches(self.weekdays, weekday);
+
+        if self.any_day || self.any_weekday {
+            day && weekday
+        } else {
+            day || weekday
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = CronError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+fn matches(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+/// Parses a field into a bit set of the values it matches.
+fn parse_field(field: &Field, text: &str) -> Result<u64, CronError> {
+    let mut bits = 0;
+
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step.parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| invalid(field, part))?;
+                (range, Some(step))
+            },
+            None => (part, None)
+        };
+
+        let (start, end) = if range == "*" {
+            (field.min, field.max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_value(field, start)?, parse_value(field, end)?)
+        } else {
+            let start = parse_value(field, range)?;
+            // A single value with a step runs until the end of the range, like `10/5`.
+            (start, if step.is_some() { field.max } else { start })
+        };
+
+        if start > end {
+            return Err(invalid(field, part));
+        }
+
+        (start..=end)
+            .step_by(step.unwrap_or(1) as usize)
+            .for_each(|value| bits |= 1 << value);
+    }
+
+    Ok(bits)
+}
+
+fn parse_value(field: &Field, text: &str) -> Result<u32, CronError> {
+    let value = match field.names.iter().position(|name| name.eq_ignore_ascii_case(text)) {
+        // Names start at the beginning of the range, `JAN` is 1 and `SUN` is 0.
+        Some(position) => position as u32 + field.min,
+        None => text.parse::<u32>().map_err(|_| invalid(field, text))?
+    };
+
+    if value < field.min || value > field.max {
+        return Err(CronError::OutOfRange {
+            field: field.name,
+            value
+        });
+    }
+
+    Ok(value)
+}
+
+fn invalid(field: &Field, value: &str) -> CronError {
+    CronError::InvalidValue {
+        field: field.name,
+        value: value.to_string()
+    }
+}
+
+/// Returns the day of the week of the given day since the unix epoch, 0 being sunday.
+fn weekday(days: i64) -> u32 {
+    // The 1st of January of 1970 was thursday.
+    (days + 4).rem_euclid(7) as u32
+}
+
+/// Converts days since the unix epoch to a (year, month, day) date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// Converts a (year, month, day) date to days since the unix epoch.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
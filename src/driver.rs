@@ -1,6 +1,19 @@
-use crate::sync::Task;
-use crossbeam_queue::SegQueue;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::error::Error;
+use crate::sync::{Outcome, Task};
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+use parking_lot::RwLock;
 use crate::periodic::PeriodicTask;
+use crate::priority::Priority;
+
+/// How many times a priority level with queued tasks can be skipped in favour of higher
+/// priorities before it is served first.
+const AGING_LIMIT: usize = 32;
+
+thread_local! {
+    static LOCAL: RefCell<Option<Local>> = const { RefCell::new(None) };
+}
 
 pub enum Either<A, B> {
     Left(A),
@@ -8,32 +21,321 @@ pub enum Either<A, B> {
 }
 
 impl Either<Task, PeriodicTask> {
-    pub fn run(self) {
+    /// Runs the task, returning how it ended if it isn't a periodic one.
+    pub fn run(self) -> Option<Outcome> {
         match self {
-            Self::Left(task) => task.run(),
-            Self::Right(task) => task.run()
+            Self::Left(task) => Some(task.run()),
+            Self::Right(task) => {
+                task.run();
+                None
+            }
         }
     }
 }
 
-/// The main queue of the pool, all task stored here will be executed by worker threads.
-#[derive(Default)]
+pub type Item = Either<Task, PeriodicTask>;
+
+/// The local queues of a worker thread, only accessible from the thread itself.
+struct Local {
+    /// The address of the driver the queues belong to.
+    driver: usize,
+    /// The slot of the stealers of the queues at the driver.
+    id: usize,
+    queues: [Worker<Item>; Priority::LEVELS]
+}
+
+/// The queues of the pool, all task stored here will be executed by worker threads.
+///
+/// Every worker thread has its own local queues, tasks spawned from inside a worker are pushed
+/// to them, while tasks spawned from outside the pool are pushed to the global injectors. When
+/// both are empty, workers steal tasks from the local queues of other workers.
+///
+/// There is a queue for each [`Priority`] level, tasks are popped from the highest priority
+/// queue, but every level counts how many times it has been skipped while having tasks, so lower
+/// priorities get to run once they reach [`AGING_LIMIT`].
 pub struct Driver {
-    pub queue: SegQueue<Either<Task, PeriodicTask>>,
+    injectors: [Injector<Item>; Priority::LEVELS],
+    stealers: RwLock<Vec<Option<[Stealer<Item>; Priority::LEVELS]>>>,
+    skipped: [AtomicUsize; Priority::LEVELS],
+    /// The number of tasks queued, including the slots reserved for tasks about to be pushed.
+    ///
+    /// Every push and pop contends on it, so it is only kept if the queue has a capacity.
+    len: AtomicUsize,
+    /// The maximum number of queued tasks.
+    capacity: Option<usize>
 }
 
 impl Driver {
-    pub fn schedule(&self, task: Either<Task, PeriodicTask>) {
-        self.queue.push(task);
+    pub fn new(capacity: Option<usize>) -> Self {
+        Self {
+            injectors: Default::default(),
+            stealers: RwLock::default(),
+            skipped: Default::default(),
+            len: AtomicUsize::new(0),
+            capacity
+        }
+    }
+
+    fn address(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Creates the local queues of the current thread.
+    pub fn register(&self) {
+        let queues = [Worker::new_fifo(), Worker::new_fifo(), Worker::new_fifo()];
+        let stealers = [queues[0].stealer(), queues[1].stealer(), queues[2].stealer()];
+
+        let mut lock = self.stealers.write();
+        let id = match lock.iter().position(Option::is_none) {
+            Some(id) => {
+                lock[id] = Some(stealers);
+                id
+            },
+            None => {
+                lock.push(Some(stealers));
+                lock.len() - 1
+            }
+        };
+        drop(lock);
+
+        LOCAL.with(|cell| {
+            *cell.borrow_mut() = Some(Local {
+                driver: self.address(),
+                id,
+                queues
+            });
+        });
+    }
+
+    /// Returns whether the current thread is a worker of this driver.
+    pub fn is_worker(&self) -> bool {
+        LOCAL.try_with(|cell| {
+            cell.borrow().as_ref().map(|local| local.driver == self.address()).unwrap_or(false)
+        }).unwrap_or(false)
+    }
+
+    /// Removes the local queues of the current thread, moving the remaining tasks to the global
+    /// injectors.
+    pub fn unregister(&self) {
+        let Some(local) = LOCAL.with(|cell| cell.borrow_mut().take()) else {
+            return;
+        };
+
+        self.stealers.write()[local.id] = None;
+
+        for (queue, injector) in local.queues.iter().zip(&self.injectors) {
+            while let Some(task) = queue.pop() {
+                injector.push(task);
+            }
+        }
+    }
+
+    /// Returns the number of queued tasks, which is only approximate if the queue has no
+    /// capacity, as it is counted while other threads push and pop tasks.
+    pub fn len(&self) -> usize {
+        if self.capacity.is_some() {
+            return self.len.load(Ordering::Acquire);
+        }
+
+        let local = self.stealers.read().iter()
+            .flatten()
+            .flat_map(|stealers| stealers.iter().map(Stealer::len))
+            .sum::<usize>();
+        self.injectors.iter().map(Injector::len).sum::<usize>() + local
+    }
+
+    /// Reserves a slot for a task if the queue has less tasks than its capacity, the task must
+    /// be then pushed using [`schedule_reserved`].
+    ///
+    /// [`schedule_reserved`]: Driver::schedule_reserved
+    pub fn reserve(&self) -> bool {
+        match self.capacity {
+            Some(capacity) => self.len.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |len| {
+                (len < capacity).then_some(len + 1)
+            }).is_ok(),
+            None => true
+        }
+    }
+
+    pub fn schedule(&self, task: Item, priority: Priority) {
+        if self.capacity.is_some() {
+            self.len.fetch_add(1, Ordering::SeqCst);
+        }
+        self.schedule_reserved(task, priority);
+    }
+
+    pub fn schedule_reserved(&self, task: Item, priority: Priority) {
+        let task = LOCAL.try_with(|cell| {
+            match cell.borrow().as_ref() {
+                Some(local) if local.driver == self.address() => {
+                    local.queues[priority.index()].push(task);
+                    None
+                },
+                _ => Some(task)
+            }
+        });
+
+        // If the current thread isn't a worker of this pool, or it is being destroyed, the task
+        // goes to the global injector.
+        match task {
+            Ok(None) => (),
+            Ok(Some(task)) => self.injectors[priority.index()].push(task),
+            Err(_) => unreachable!("Task moved into a destroyed thread local")
+        }
+    }
+
+    /// Like [`schedule_reserved`], but pushes a whole batch of tasks, checking only once where
+    /// they go.
+    ///
+    /// [`schedule_reserved`]: Driver::schedule_reserved
+    pub fn schedule_batch_reserved(&self, tasks: Vec<Item>, priority: Priority) {
+        let tasks = LOCAL.try_with(|cell| {
+            match cell.borrow().as_ref() {
+                Some(local) if local.driver == self.address() => {
+                    let queue = &local.queues[priority.index()];
+                    tasks.into_iter().for_each(|task| queue.push(task));
+                    None
+                },
+                _ => Some(tasks)
+            }
+        });
+
+        match tasks {
+            Ok(None) => (),
+            Ok(Some(tasks)) => {
+                let injector = &self.injectors[priority.index()];
+                tasks.into_iter().for_each(|task| injector.push(task));
+            },
+            Err(_) => unreachable!("Task moved into a destroyed thread local")
+        }
+    }
+
+    pub fn pop(&self) -> Option<Item> {
+        let task = LOCAL.try_with(|cell| {
+            let borrow = cell.borrow();
+            let local = borrow.as_ref().filter(|local| local.driver == self.address());
+            self.pop_with(local)
+        }).unwrap_or_else(|_| self.pop_with(None));
+
+        if task.is_some() && self.capacity.is_some() {
+            self.len.fetch_sub(1, Ordering::SeqCst);
+        }
+        task
+    }
+
+    fn pop_with(&self, local: Option<&Local>) -> Option<Item> {
+        // Stealing from other workers contends with them, so it is only done once every local
+        // queue and injector is empty.
+        self.pop_levels(local, false).or_else(|| self.pop_levels(local, true))
+    }
+
+    /// Pops a task following the priorities, either from the local queues and the injectors or
+    /// stealing it from other workers.
+    fn pop_levels(&self, local: Option<&Local>, steal: bool) -> Option<Item> {
+        let pop_level = |level| match steal {
+            true => self.steal_level(level, local),
+            false => self.pop_level(level, local)
+        };
+
+        for (level, skipped) in self.skipped.iter().enumerate() {
+            if skipped.load(Ordering::Relaxed) >= AGING_LIMIT {
+                skipped.store(0, Ordering::Relaxed);
+                if let Some(task) = pop_level(level) {
+                    return Some(task);
+                }
+            }
+        }
+
+        for level in (0..Priority::LEVELS).rev() {
+            if let Some(task) = pop_level(level) {
+                (0..level)
+                    .filter(|lower| !self.level_is_empty(*lower, local))
+                    .for_each(|lower| {
+                        self.skipped[lower].fetch_add(1, Ordering::Relaxed);
+                    });
+
+                return Some(task);
+            }
+        }
+
+        None
+    }
+
+    /// Pops a task of the given level, first from the local queue and then from the global
+    /// injector.
+    fn pop_level(&self, level: usize, local: Option<&Local>) -> Option<Item> {
+        if let Some(task) = local.and_then(|local| local.queues[level].pop()) {
+            return Some(task);
+        }
+
+        let injector = &self.injectors[level];
+        loop {
+            let steal = match local {
+                Some(local) => injector.steal_batch_and_pop(&local.queues[level]),
+                None => injector.steal()
+            };
+
+            match steal {
+                Steal::Success(task) => return Some(task),
+                Steal::Retry => (),
+                Steal::Empty => return None
+            }
+        }
+    }
+
+    /// Steals a task of the given level from the local queues of other workers.
+    fn steal_level(&self, level: usize, local: Option<&Local>) -> Option<Item> {
+        loop {
+            let mut retry = false;
+
+            let stealers = self.stealers.read();
+            // Start after our own slot so workers don't all target the same queue, skipping it.
+            let (start, count) = match local {
+                Some(local) => (local.id + 1, stealers.len() - 1),
+                None => (0, stealers.len())
+            };
+
+            for other in stealers.iter().cycle().skip(start).take(count).flatten() {
+                let steal = match local {
+                    Some(local) => other[level].steal_batch_and_pop(&local.queues[level]),
+                    None => other[level].steal()
+                };
+
+                match steal {
+                    Steal::Success(task) => return Some(task),
+                    Steal::Retry => retry = true,
+                    Steal::Empty => ()
+                }
+            }
+
+            if !retry {
+                return None;
+            }
+        }
+    }
+
+    fn level_is_empty(&self, level: usize, local: Option<&Local>) -> bool {
+        self.injectors[level].is_empty()
+            && local.map(|local| local.queues[level].is_empty()).unwrap_or(true)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.injectors.iter().all(Injector::is_empty)
+            && self.stealers.read().iter()
+                .flatten()
+                .all(|stealers| stealers.iter().all(Stealer::is_empty))
     }
 
-    pub fn clear(&self) {
-        while let Some(item) = self.queue.pop() {
-            match item {
-                Either::Left(task) => task.abort(),
-                _ => ()
+    /// Aborts all the queued tasks with the given error, returning how many were aborted.
+    pub fn clear(&self, error: fn() -> Error) -> usize {
+        let mut aborted = 0;
+        while let Some(item) = self.pop() {
+            if let Either::Left(task) = item {
+                task.abort(error());
+                aborted += 1;
             }
         }
+        aborted
     }
 
 }
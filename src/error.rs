@@ -1,21 +1,211 @@
 use std::any::Any;
+use std::fmt;
+use std::panic;
+use crate::cancel::TaskId;
 
 /// The error that can be returned after spawning a task.
-/// This will be only seen when the provided task panics or the pool is stopped before the task
-/// could be executed.
+/// This will be only seen when the provided task panics, is aborted or the pool is stopped before
+/// the task could be executed.
 #[derive(Debug)]
 pub enum Error {
     /// The task has panicked and the error is returned
-    Panicked(Box<dyn Any + Send + 'static>),
-    /// The task has been aborted, this is seen when the pool was stopped and the task didn't
-    /// get to be executed before stopping.
-    Aborted
+    Panicked(Panic),
+    /// The task has been aborted using its handle before it could be executed, or it was
+    /// dropped without running, like when the worker thread that took it dies because a hook
+    /// panicked.
+    Aborted,
+    /// The task was asked to stop using its handle while it was running.
+    Cancelled,
+    /// The pool was shut down using [`shutdown_timeout`] and the timeout passed before the task
+    /// could be executed.
+    ///
+    /// [`shutdown_timeout`]: crate::handle::Handle::shutdown_timeout
+    TimedOut,
+    /// The pool was shut down before the task could be executed.
+    PoolShutdown
 }
 
+impl Error {
+    /// Returns whether the task panicked.
+    pub fn is_panic(&self) -> bool {
+        matches!(self, Self::Panicked(_))
+    }
+
+    /// Returns the panic of the task, if it panicked.
+    pub fn panic(&self) -> Option<&Panic> {
+        match self {
+            Self::Panicked(panic) => Some(panic),
+            _ => None
+        }
+    }
+
+    /// Returns the panic of the task, if it panicked.
+    pub fn into_panic(self) -> Option<Panic> {
+        match self {
+            Self::Panicked(panic) => Some(panic),
+            _ => None
+        }
+    }
+
+    /// Returns the message the task panicked with, if it panicked with a `&str` or a `String`.
+    pub fn panic_message(&self) -> Option<&str> {
+        self.panic().and_then(Panic::message)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Panicked(panic) => fmt::Display::fmt(panic, f),
+            Self::Aborted => f.write_str("task aborted"),
+            Self::Cancelled => f.write_str("task cancelled"),
+            Self::TimedOut => f.write_str("task aborted, the pool shutdown timed out"),
+            Self::PoolShutdown => f.write_str("task aborted, the pool was shut down")
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl From<Box<dyn Any + Send + 'static>> for Error {
     fn from(err: Box<dyn Any + Send + 'static>) -> Self {
-        Error::Panicked(err)
+        Error::Panicked(Panic::new(err, None))
+    }
+}
+
+/// The information about a task that panicked.
+pub struct Panic {
+    payload: Box<dyn Any + Send + 'static>,
+    task: Option<TaskId>,
+    thread: Option<String>
+}
+
+impl Panic {
+    /// Creates the panic from its payload, taking the name of the current thread.
+    pub(crate) fn new(payload: Box<dyn Any + Send + 'static>, task: Option<TaskId>) -> Self {
+        Self {
+            payload,
+            task,
+            thread: std::thread::current().name().map(ToString::to_string)
+        }
+    }
+
+    /// Returns the message the task panicked with, if it panicked with a `&str` or a `String`,
+    /// like [`panic!`] does.
+    ///
+    /// [`panic!`]: std::panic!
+    pub fn message(&self) -> Option<&str> {
+        self.payload.downcast_ref::<&str>()
+            .copied()
+            .or_else(|| self.payload.downcast_ref::<String>().map(String::as_str))
+    }
+
+    /// Returns the value the task panicked with.
+    pub fn payload(&self) -> &(dyn Any + Send + 'static) {
+        &*self.payload
+    }
+
+    /// Returns the value the task panicked with.
+    pub fn into_payload(self) -> Box<dyn Any + Send + 'static> {
+        self.payload
+    }
+
+    /// Returns the id of the task, or [`None`] if it was spawned without a handle.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn task_id(&self) -> Option<TaskId> {
+        self.task
+    }
+
+    /// Returns the name of the thread the task panicked on.
+    pub fn thread_name(&self) -> Option<&str> {
+        self.thread.as_deref()
     }
+
+    /// Resumes the panic on the current thread.
+    pub fn resume(self) -> ! {
+        panic::resume_unwind(self.payload)
+    }
+}
+
+impl fmt::Debug for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Panic")
+            .field("message", &self.message())
+            .field("task", &self.task)
+            .field("thread", &self.thread)
+            .finish()
+    }
+}
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("task")?;
+        if let Some(task) = self.task {
+            write!(f, " {}", task)?;
+        }
+        f.write_str(" panicked")?;
+        if let Some(thread) = &self.thread {
+            write!(f, " on thread '{}'", thread)?;
+        }
+        match self.message() {
+            Some(message) => write!(f, ": {}", message),
+            None => Ok(())
+        }
+    }
+}
+
+/// The error returned by [`try_spawn`] and the like when a task couldn't be spawned, giving
+/// back the task.
+///
+/// [`try_spawn`]: crate::handle::Handle::try_spawn
+pub enum SpawnError<R> {
+    /// The current thread is not inside the context of a pool.
+    NoContext(R),
+    /// The pool is being shut down or has stopped.
+    Shutdown(R),
+    /// The queue of the pool is full.
+    QueueFull(R)
 }
 
+impl<R> SpawnError<R> {
+    /// Returns the task that couldn't be spawned.
+    pub fn into_inner(self) -> R {
+        match self {
+            Self::NoContext(task) | Self::Shutdown(task) | Self::QueueFull(task) => task
+        }
+    }
+
+    /// Replaces the task of the error.
+    pub(crate) fn map<U>(self, f: impl FnOnce(R) -> U) -> SpawnError<U> {
+        match self {
+            Self::NoContext(task) => SpawnError::NoContext(f(task)),
+            Self::Shutdown(task) => SpawnError::Shutdown(f(task)),
+            Self::QueueFull(task) => SpawnError::QueueFull(f(task))
+        }
+    }
+}
+
+impl<R> fmt::Debug for SpawnError<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoContext(_) => f.write_str("NoContext(..)"),
+            Self::Shutdown(_) => f.write_str("Shutdown(..)"),
+            Self::QueueFull(_) => f.write_str("QueueFull(..)")
+        }
+    }
+}
+
+impl<R> fmt::Display for SpawnError<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoContext(_) => f.write_str("not inside the context of a pool"),
+            Self::Shutdown(_) => f.write_str("the pool was shut down"),
+            Self::QueueFull(_) => f.write_str("the queue of the pool is full")
+        }
+    }
+}
+
+impl<R> std::error::Error for SpawnError<R> {}
+
 pub type Result<T> = ::std::result::Result<T, Error>;
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Weak};
+use std::sync::atomic::{fence, AtomicU8, Ordering};
+use std::task::{Context, Poll, Wake, Waker};
+use parking_lot::Mutex;
+use crate::cancel::{CancellationToken, TaskId};
+use crate::core::Core;
+use crate::error::{Error, Panic};
+use crate::signal::Blocker;
+use crate::sync::{Outcome, Task};
+use crate::wait::Sender;
+
+/// The future is waiting to be woken up.
+const IDLE: u8 = 0;
+/// The future is queued to be polled.
+const SCHEDULED: u8 = 1;
+/// The future is being polled.
+const POLLING: u8 = 2;
+/// The future was woken up while being polled, so it must be polled again.
+const NOTIFIED: u8 = 3;
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// A future spawned into the pool that can be aborted without knowing its output type.
+trait Abort: Send + Sync {
+    /// Aborts the future unless it is being polled, returning whether it was aborted.
+    fn try_abort(&self, error: fn() -> Error) -> bool;
+}
+
+/// The futures spawned into the pool that haven't completed yet.
+///
+/// Idle futures aren't queued anywhere, only their wakers keep them alive, so the pool keeps
+/// track of them to resolve their handles when it stops.
+#[derive(Default)]
+pub struct Futures {
+    live: Mutex<HashMap<TaskId, Weak<dyn Abort>>>
+}
+
+impl Futures {
+    fn insert(&self, id: TaskId, task: Weak<dyn Abort>) {
+        self.live.lock().insert(id, task);
+    }
+
+    fn remove(&self, id: TaskId) {
+        self.live.lock().remove(&id);
+    }
+
+    /// Aborts every future that hasn't completed yet with the given error, returning how many
+    /// were aborted.
+    ///
+    /// Futures being polled are skipped, the thread polling them aborts them once the poll
+    /// returns, as the pool isn't running anymore.
+    pub fn abort_all(&self, error: fn() -> Error) -> usize {
+        // Pairs with the fence after a poll, so either the poll sees the pool stopped or we see
+        // the future unlocked.
+        fence(Ordering::SeqCst);
+        let live = std::mem::take(&mut *self.live.lock());
+        live.into_values()
+            .filter_map(|task| task.upgrade())
+            .filter(|task| task.try_abort(error))
+            .count()
+    }
+}
+
+/// A future spawned into the pool, it is polled by the worker threads and queued again every
+/// time its waker is woken.
+pub struct FutureTask<T> {
+    core: Arc<Core>,
+    state: AtomicU8,
+    /// The future, taken once it completes or gets aborted.
+    future: Mutex<Option<BoxFuture<T>>>,
+    sender: Sender<T>,
+    token: CancellationToken
+}
+
+impl<T: Send + 'static> FutureTask<T> {
+    /// Creates the task, returning its waker along with the first poll to schedule.
+    pub fn create<F>(
+        core: Arc<Core>,
+        future: F,
+        sender: Sender<T>,
+        token: CancellationToken
+    ) -> (Waker, Task)
+    where
+        F: Future<Output = T> + Send + 'static
+    {
+        let task = Arc::new(Self {
+            core,
+            state: AtomicU8::new(SCHEDULED),
+            future: Mutex::new(Some(Box::pin(future))),
+            sender,
+            token
+        });
+        let weak = Arc::downgrade(&task);
+        task.core.futures.insert(task.token.id(), weak);
+        let poll = task.task();
+        (Waker::from(task), poll)
+    }
+
+    /// Creates the task that polls the future once executed.
+    fn task(self: &Arc<Self>) -> Task {
+        let poll = Arc::clone(self);
+        let abort = Arc::clone(self);
+        Task::from_fn(move || poll.poll(), move |error| abort.abort(error))
+    }
+
+    fn poll(self: Arc<Self>) -> Outcome {
+        self.state.store(POLLING, Ordering::Release);
+        let mut slot = self.future.lock();
+        let future = match slot.as_mut() {
+            Some(future) => future,
+            None => return Outcome::Aborted
+        };
+
+        // The handle aborted the future before it was polled for the first time, and it already
+        // set the result.
+        if !self.token.start() && self.token.is_aborted() {
+            *slot = None;
+            return Outcome::Aborted;
+        }
+
+        if self.token.is_cancelled() {
+            *slot = None;
+            self.token.finish();
+            self.set_result(Err(Error::Cancelled));
+            return Outcome::Completed;
+        }
+
+        let waker = Waker::from(Arc::clone(&self));
+        let mut cx = Context::from_waker(&waker);
+        let res = {
+            let _guard = self.token.enter();
+            catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(&mut cx)))
+        };
+
+        let (res, outcome) = match res {
+            Ok(Poll::Pending) => {
+                drop(slot);
+                let idle = self.state
+                    .compare_exchange(POLLING, IDLE, Ordering::AcqRel, Ordering::Acquire);
+                if idle.is_err() {
+                    // Woken up while being polled, so queue it again.
+                    self.state.store(SCHEDULED, Ordering::Release);
+                    self.core.reschedule(self.task());
+                    return Outcome::Pending;
+                }
+
+                // The pool may have stopped while we were polling, skipping this future.
+                fence(Ordering::SeqCst);
+                if !self.core.is_running() {
+                    self.abort(Error::PoolShutdown);
+                }
+                return Outcome::Pending;
+            },
+            Ok(Poll::Ready(output)) => (Ok(output), Outcome::Completed),
+            Err(payload) => {
+                let error = Error::Panicked(Panic::new(payload, Some(self.token.id())));
+                crate::sync::report_panic(&error);
+                (Err(error), Outcome::Panicked)
+            }
+        };
+
+        *slot = None;
+        drop(slot);
+        if self.token.finish() {
+            self.set_result(Err(Error::Cancelled));
+        } else {
+            self.set_result(res);
+        }
+        if let Outcome::Panicked = outcome {
+            crate::sync::apply_panic_policy();
+        }
+        outcome
+    }
+
+    /// Drops the future without completing it, resolving its handle with the given error.
+    fn abort(&self, error: Error) {
+        let future = self.future.lock().take();
+        if future.is_some() {
+            self.resolve_aborted(error);
+        }
+    }
+
+    /// Resolves the handle of the future just taken out of its slot with the given error.
+    fn resolve_aborted(&self, error: Error) {
+        // If the handle aborted it before it was polled, the result is already set.
+        if self.token.abort() || !self.token.is_aborted() {
+            self.set_result(Err(error));
+        }
+    }
+
+    fn set_result(&self, result: crate::error::Result<T>) {
+        self.core.futures.remove(self.token.id());
+        self.sender.set(result);
+    }
+
+    fn schedule(self: &Arc<Self>) {
+        let res = self.state.fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+            match state {
+                IDLE => Some(SCHEDULED),
+                POLLING => Some(NOTIFIED),
+                _ => None
+            }
+        });
+
+        if res == Ok(IDLE) {
+            self.core.reschedule(self.task());
+        }
+    }
+}
+
+impl<T: Send + 'static> Abort for FutureTask<T> {
+    fn try_abort(&self, error: fn() -> Error) -> bool {
+        let future = match self.future.try_lock() {
+            Some(mut future) => future.take(),
+            None => return false
+        };
+
+        match future {
+            Some(_) => {
+                self.resolve_aborted(error());
+                true
+            },
+            None => false
+        }
+    }
+}
+
+impl<T> Drop for FutureTask<T> {
+    fn drop(&mut self) {
+        self.core.futures.remove(self.token.id());
+    }
+}
+
+impl<T: Send + 'static> Wake for FutureTask<T> {
+    fn wake(self: Arc<Self>) {
+        self.schedule();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.schedule();
+    }
+}
+
+/// Drives the future to completion on the current thread, returning its output.
+///
+/// When called from a worker thread, the thread keeps executing queued tasks while the future
+/// isn't ready, so the future can wait for tasks of its own pool.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let blocker = Blocker::new();
+    let waker = Waker::from(Arc::clone(blocker.signal()));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        blocker.wait();
+    }
+}
@@ -1,11 +1,39 @@
+use std::future::Future;
+use std::io;
 use std::marker::PhantomData;
+use std::pin::Pin;
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::Ordering;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use crate::builder::Backpressure;
+use crate::cancel::CancellationToken;
 use crate::core::Core;
+use crate::cron::{CronError, Schedule};
+use crate::error::SpawnError;
+use crate::future::FutureTask;
 use crate::{JoinHandle, Runnable};
+use crate::join::PeriodicHandle;
+use crate::join_set::JoinSet;
+use crate::metrics::Metrics;
 use crate::periodic::PeriodicTask;
+use crate::priority::Priority;
+use crate::scope::Scope;
 use crate::sync::Task;
-use crate::wait::{Inner, Waiter};
+use crate::timer::TimerKey;
+use crate::wait;
+
+/// The lifecycle state of a pool, returned by [`Handle::state`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    /// The pool accepts and executes tasks.
+    Running,
+    /// The pool is being shut down gracefully, it doesn't accept new tasks but executes the
+    /// queued ones.
+    Draining,
+    /// The pool has stopped.
+    Stopped
+}
 
 /// Handle used to operate the pool.
 #[derive(Clone)]
@@ -33,11 +61,272 @@ impl Handle {
     where
         R: Runnable
     {
-        let inner = Inner::<R::Output>::new();
-        let task = Task::new(runnable, Some(inner));
-        self.core.schedule(task);
+        self.spawn_with_priority(runnable, Priority::Normal)
+    }
+
+    /// Like [`spawn`], but the task is executed with the given [`priority`].
+    ///
+    /// [`spawn`]: Handle::spawn
+    /// [`priority`]: crate::priority::Priority
+    pub fn spawn_with_priority<R>(&self, runnable: R, priority: Priority) -> JoinHandle<R::Output>
+    where
+        R: Runnable
+    {
+        self.reserve();
+        self.spawn_reserved(runnable, priority)
+    }
+
+    /// Tries to spawn a new task into the pool, returning a [`SpawnError`] with the task if the
+    /// pool was shut down or its queue is full, instead of panicking or blocking.
+    ///
+    /// [`SpawnError`]: crate::error::SpawnError
+    pub fn try_spawn<R>(&self, runnable: R) -> Result<JoinHandle<R::Output>, SpawnError<R>>
+    where
+        R: Runnable
+    {
+        match self.core.try_reserve() {
+            Ok(()) => Ok(self.spawn_reserved(runnable, Priority::Normal)),
+            Err(error) => Err(error.map(|_| runnable))
+        }
+    }
+
+    /// Like [`try_spawn`], but doesn't return a handle, as [`spawn_detached`] does.
+    ///
+    /// [`try_spawn`]: Handle::try_spawn
+    /// [`spawn_detached`]: Handle::spawn_detached
+    pub fn try_spawn_detached<R>(&self, runnable: R) -> Result<(), SpawnError<R>>
+    where
+        R: Runnable
+    {
+        match self.core.try_reserve() {
+            Ok(()) => {
+                self.core.schedule(Task::new(runnable, None, None), Priority::Normal);
+                Ok(())
+            },
+            Err(error) => Err(error.map(|_| runnable))
+        }
+    }
+
+    /// Spawns a new task into the pool, returning a future that waits asynchronously until
+    /// there is room for the task at the queue, resolving to the [`handle`] of the task.
+    ///
+    /// [`handle`]: crate::join::JoinHandle
+    pub fn spawn_async<R>(&self, runnable: R) -> SpawnAsync<R>
+    where
+        R: Runnable
+    {
+        SpawnAsync {
+            handle: self.clone(),
+            runnable: Some(runnable)
+        }
+    }
+
+    /// Spawns a new task into the pool that will be executed after the given delay, returning a
+    /// [`handle`] that can be used to retrieve the output.
+    ///
+    /// Aborting the task before the delay passes removes it from the pool.
+    ///
+    /// [`handle`]: crate::join::JoinHandle
+    pub fn spawn_after<R>(&self, delay: Duration, runnable: R) -> JoinHandle<R::Output>
+    where
+        R: Runnable
+    {
+        self.spawn_at(Instant::now() + delay, runnable)
+    }
+
+    /// Spawns a new task into the pool that will be executed once the given instant is reached,
+    /// returning a [`handle`] that can be used to retrieve the output.
+    ///
+    /// Aborting the task before the instant is reached removes it from the pool.
+    ///
+    /// [`handle`]: crate::join::JoinHandle
+    pub fn spawn_at<R>(&self, at: Instant, runnable: R) -> JoinHandle<R::Output>
+    where
+        R: Runnable
+    {
+        let (sender, waiter) = wait::channel();
+        let token = CancellationToken::new();
+        let task = Task::new(runnable, Some(sender), Some(token.clone()));
+        let id = self.core.schedule_at(task, at);
+        JoinHandle {
+            inner: waiter,
+            token,
+            timer: Some(TimerKey {
+                core: Arc::clone(&self.core),
+                id
+            }),
+            waker: None
+        }
+    }
+
+    /// Spawns a future into the pool, returning a [`handle`] that can be used to retrieve its
+    /// output.
+    ///
+    /// The future is polled by the worker threads, and queued again every time it is woken up.
+    ///
+    /// [`handle`]: crate::join::JoinHandle
+    pub fn spawn_future<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static
+    {
+        self.reserve();
+        let (sender, waiter) = wait::channel();
+        let token = CancellationToken::new();
+        let (waker, task) = FutureTask::create(Arc::clone(&self.core), future, sender, token.clone());
+        self.core.schedule(task, Priority::Normal);
+        JoinHandle {
+            inner: waiter,
+            token,
+            timer: None,
+            waker: Some(waker)
+        }
+    }
+
+    /// Creates a scope to spawn tasks that can borrow from the current stack frame, in the style
+    /// of [`std::thread::scope`].
+    ///
+    /// Every task spawned into the scope is waited for before this returns, and if any of them
+    /// panicked and its [`handle`] didn't retrieve the panic, this panics once all of them finish.
+    /// If called from a worker thread, the thread executes queued tasks while it waits.
+    ///
+    /// [`std::thread::scope`]: std::thread::scope
+    /// [`handle`]: crate::scope::ScopedJoinHandle
+    pub fn scope<'env, F, T>(&self, f: F) -> T
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T
+    {
+        crate::scope::scope(self, f)
+    }
+
+    /// Creates an empty [`JoinSet`] whose tasks are spawned into this pool.
+    ///
+    /// [`JoinSet`]: crate::join_set::JoinSet
+    pub fn join_set<T: Send + 'static>(&self) -> JoinSet<T> {
+        JoinSet::new(self)
+    }
+
+    /// Runs both closures potentially in parallel, returning both results.
+    ///
+    /// The first closure runs in the current thread while the second one is queued. If no thread
+    /// took the second one once the first returns, it also runs here, otherwise the thread waits
+    /// for it, executing queued tasks meanwhile if it is a worker. If the queue is full or the
+    /// pool is shut down, both run here one after the other. If any of the closures panics, the
+    /// panic is propagated once both finished.
+    pub fn join<A, B, RA, RB>(&self, a: A, b: B) -> (RA, RB)
+    where
+        A: FnOnce() -> RA + Send,
+        B: FnOnce() -> RB + Send,
+        RA: Send,
+        RB: Send
+    {
+        crate::scope::join(self, a, b)
+    }
+
+    /// Applies the function to every item in parallel, returning the outputs in the same order as
+    /// the items.
+    ///
+    /// The items can come from any iterator, like ranges or slices, and are split into parts
+    /// based on their count and the number of threads of the pool. Parts are processed using
+    /// [`join`], so idle threads pick the pending ones.
+    ///
+    /// [`join`]: Handle::join
+    pub fn par_map<I, F, U>(&self, items: I, f: F) -> Vec<U>
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        F: Fn(I::Item) -> U + Sync,
+        U: Send
+    {
+        crate::par::map(self, items, f)
+    }
+
+    /// Calls the function with every item in parallel.
+    ///
+    /// See [`par_map`] for how the items are split.
+    ///
+    /// [`par_map`]: Handle::par_map
+    pub fn par_for_each<I, F>(&self, items: I, f: F)
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        F: Fn(I::Item) + Sync
+    {
+        crate::par::for_each(self, items, f)
+    }
+
+    /// Applies the function to every chunk of `size` elements of the slice in parallel, returning
+    /// the outputs in the same order as the chunks, the last chunk may be shorter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub fn par_chunks<T, F, U>(&self, slice: &[T], size: usize, f: F) -> Vec<U>
+    where
+        T: Sync,
+        F: Fn(&[T]) -> U + Sync,
+        U: Send
+    {
+        crate::par::map(self, slice.chunks(size), f)
+    }
+
+    /// Reduces the items in parallel using the given operation, which must be associative.
+    ///
+    /// Each part of the items starts from the value returned by `identity`, and the results of
+    /// the parts are combined keeping the order of the items, returning `identity()` if there
+    /// are no items.
+    pub fn par_reduce<I, ID, F>(&self, items: I, identity: ID, op: F) -> I::Item
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        ID: Fn() -> I::Item + Sync,
+        F: Fn(I::Item, I::Item) -> I::Item + Sync
+    {
+        crate::par::reduce(self, items, identity, op)
+    }
+
+    /// Sorts the slice in parallel, the sort is stable.
+    pub fn par_sort<T>(&self, slice: &mut [T])
+    where
+        T: Ord + Send
+    {
+        crate::par::sort_by(self, slice, &T::cmp)
+    }
+
+    /// Like [`par_sort`], but sorts the slice with the given comparator function.
+    ///
+    /// [`par_sort`]: Handle::par_sort
+    pub fn par_sort_by<T, F>(&self, slice: &mut [T], compare: F)
+    where
+        T: Send,
+        F: Fn(&T, &T) -> std::cmp::Ordering + Sync
+    {
+        crate::par::sort_by(self, slice, &compare)
+    }
+
+    /// Reserves a slot at the queue as set by the [`backpressure`] of the pool.
+    ///
+    /// [`backpressure`]: crate::builder::WorkerPoolBuilder::backpressure
+    pub(crate) fn reserve(&self) {
+        let block = self.core.config.backpressure == Backpressure::Block;
+        if !self.core.reserve(block) {
+            panic!("Threadpool queue full");
+        }
+    }
+
+    fn spawn_reserved<R>(&self, runnable: R, priority: Priority) -> JoinHandle<R::Output>
+    where
+        R: Runnable
+    {
+        let (sender, waiter) = wait::channel();
+        let token = CancellationToken::new();
+        let task = Task::new(runnable, Some(sender), Some(token.clone()));
+        self.core.schedule(task, priority);
         JoinHandle {
-            inner: Waiter::new(inner)
+            inner: waiter,
+            token,
+            timer: None,
+            waker: None
         }
     }
 
@@ -49,32 +338,199 @@ impl Handle {
     where
         R: Runnable
     {
-        let task = Task::new(runnable, None);
-        self.core.schedule(task);
+        self.spawn_detached_with_priority(runnable, Priority::Normal)
+    }
+
+    /// Like [`spawn_detached`], but the task is executed with the given [`priority`].
+    ///
+    /// [`spawn_detached`]: Handle::spawn_detached
+    /// [`priority`]: crate::priority::Priority
+    pub fn spawn_detached_with_priority<R>(&self, runnable: R, priority: Priority)
+    where
+        R: Runnable
+    {
+        self.reserve();
+        let task = Task::new(runnable, None, None);
+        self.core.schedule(task, priority);
+    }
+
+    /// Spawns a batch of tasks into the pool, returning their [`handles`] in the same order.
+    ///
+    /// The tasks are queued at once, waking up at most as many threads as tasks, which is much
+    /// cheaper than spawning them one by one. If the queue has a capacity, the tasks are queued
+    /// in as many batches as needed to follow the [`backpressure`] of the pool.
+    ///
+    /// [`handles`]: crate::join::JoinHandle
+    /// [`backpressure`]: crate::builder::WorkerPoolBuilder::backpressure
+    pub fn spawn_batch<I>(&self, runnables: I) -> Vec<JoinHandle<<I::Item as Runnable>::Output>>
+    where
+        I: IntoIterator,
+        I::Item: Runnable
+    {
+        let mut handles = Vec::new();
+        self.schedule_batch(runnables, |runnable| {
+            let (sender, waiter) = wait::channel();
+            let token = CancellationToken::new();
+            handles.push(JoinHandle {
+                inner: waiter,
+                token: token.clone(),
+                timer: None,
+                waker: None
+            });
+            Task::new(runnable, Some(sender), Some(token))
+        });
+        handles
+    }
+
+    /// Like [`spawn_batch`], but doesn't return the handles, as [`spawn_detached`] does.
+    ///
+    /// [`spawn_batch`]: Handle::spawn_batch
+    /// [`spawn_detached`]: Handle::spawn_detached
+    pub fn spawn_detached_batch<I>(&self, runnables: I)
+    where
+        I: IntoIterator,
+        I::Item: Runnable
+    {
+        self.schedule_batch(runnables, |runnable| Task::new(runnable, None, None));
+    }
+
+    /// Schedules the tasks created from the runnables in batches, a batch is queued once the
+    /// queue is full, so the slots reserved for it don't block the ones waiting for room.
+    fn schedule_batch<I, F>(&self, runnables: I, mut task: F)
+    where
+        I: IntoIterator,
+        F: FnMut(I::Item) -> Task
+    {
+        let runnables = runnables.into_iter();
+        let mut batch = Vec::with_capacity(runnables.size_hint().0);
+
+        for runnable in runnables {
+            if self.core.try_reserve().is_err() {
+                let full = std::mem::take(&mut batch);
+                if !full.is_empty() {
+                    self.core.schedule_batch(full, Priority::Normal);
+                }
+                // Blocks or panics as spawning a single task does.
+                self.reserve();
+            }
+            batch.push(task(runnable));
+        }
+
+        if !batch.is_empty() {
+            self.core.schedule_batch(batch, Priority::Normal);
+        }
     }
 
     /// Spawns a new task that will be executed periodically by the thread pool every specified time
-    /// and the specified amount of times.
-    pub fn spawn_periodic<T>(&self, task: T, every: Duration, times: Option<usize>)
+    /// and the specified amount of times, returning a [`handle`] that can be used to control it.
+    ///
+    /// [`handle`]: crate::join::PeriodicHandle
+    pub fn spawn_periodic<T>(&self, task: T, every: Duration, times: Option<usize>) -> PeriodicHandle
     where
         T: Fn() + Send + 'static
     {
         let task = PeriodicTask::new(self.clone(), task, every, times);
+        let shared = task.shared();
+        self.core.schedule_periodical(task);
+        PeriodicHandle {
+            shared
+        }
+    }
+
+    /// Spawns a new task that will be executed at the times given by the cron expression,
+    /// returning a [`handle`] that can be used to control it, or an error if the expression is
+    /// not valid.
+    ///
+    /// See [`Schedule`] for the supported syntax, the interval of the returned handle has no
+    /// effect on the task.
+    ///
+    /// [`handle`]: crate::join::PeriodicHandle
+    /// [`Schedule`]: crate::cron::Schedule
+    pub fn spawn_cron<T>(&self, expr: &str, task: T) -> Result<PeriodicHandle, CronError>
+    where
+        T: Fn() + Send + 'static
+    {
+        let schedule = Schedule::parse(expr)?;
+        let task = PeriodicTask::with_cron(self.clone(), task, schedule)
+            .ok_or(CronError::Unsatisfiable)?;
+        let shared = task.shared();
         self.core.schedule_periodical(task);
+        Ok(PeriodicHandle {
+            shared
+        })
+    }
+
+    /// Returns the number of worker threads the pool currently has.
+    pub fn threads(&self) -> usize {
+        self.core.threads.load(Ordering::Acquire)
+    }
+
+    /// Takes a snapshot of the metrics of the pool, like the number of queued tasks or how busy
+    /// each worker thread is.
+    pub fn metrics(&self) -> Metrics {
+        self.core.metrics()
+    }
+
+    /// Resizes the pool to the given number of threads, spawning the missing ones immediately,
+    /// while the extra threads retire once they finish their current task.
+    ///
+    /// This also sets both the minimum and maximum number of threads of the pool.
+    pub fn set_threads(&self, threads: usize) -> io::Result<()> {
+        self.core.set_threads(threads)
     }
 
-    /// Shuts down the pool, waiting for all threads to exit.
+    /// Returns the lifecycle state of the pool.
+    pub fn state(&self) -> State {
+        self.core.state()
+    }
+
+    /// Returns whether the pool is being shut down or has stopped, so it doesn't accept new tasks.
+    pub fn is_shutdown(&self) -> bool {
+        self.state() != State::Running
+    }
+
+    /// Shuts down the pool, aborting all the queued tasks and waiting for all threads to exit.
+    ///
+    /// Futures that haven't completed are aborted as well, their handles get [`PoolShutdown`].
+    /// If called from inside a worker thread, the thread exits once the current task returns, and
+    /// the other threads aren't waited for, as they may be waiting for the current task.
+    ///
+    /// [`PoolShutdown`]: crate::error::Error::PoolShutdown
     pub fn shutdown(self) {
         self.core.shutdown();
     }
 
+    /// Shuts down the pool gracefully, new tasks are not accepted anymore but the queued ones
+    /// are executed before stopping, waiting for all threads to exit.
+    ///
+    /// Periodic tasks are cancelled, and tasks spawned with a delay that didn't reach it yet and
+    /// futures still waiting once the queue is empty are aborted with [`PoolShutdown`], while
+    /// tasks being executed can still spawn other tasks. If called from inside a worker thread,
+    /// the thread executes queued tasks as well.
+    ///
+    /// [`PoolShutdown`]: crate::error::Error::PoolShutdown
+    pub fn shutdown_graceful(self) {
+        self.core.shutdown_graceful();
+    }
+
+    /// Like [`shutdown_graceful`], but if the queued tasks are not finished before the timeout,
+    /// the remaining ones are aborted like in [`shutdown`].
+    ///
+    /// Returns whether all the queued tasks could be executed before the timeout.
+    ///
+    /// [`shutdown_graceful`]: Handle::shutdown_graceful
+    /// [`shutdown`]: Handle::shutdown
+    pub fn shutdown_timeout(self, timeout: Duration) -> bool {
+        self.core.shutdown_timeout(timeout)
+    }
+
     /// Enters the context of the pool the handle belongs to, thus allowing to use directly
     /// [`spawn`]/[`spawn_detached`]/[`spawn_periodic`] without using the handle.
     ///
     /// [`spawn`]: crate::spawn
     /// [`spawn_detached`]: crate::spawn_detached
     /// [`spawn_periodic`]: crate::spawn_periodic
-    pub fn enter_context(&self) -> ContextGuard {
+    pub fn enter_context(&self) -> ContextGuard<'_> {
         if crate::context::try_get().is_some() {
             panic!("Already inside the context of a worker pool");
         }
@@ -84,6 +540,36 @@ impl Handle {
     }
 }
 
+/// Future returned by [`spawn_async`], resolves to the [`handle`] of the task once it could
+/// be spawned.
+///
+/// [`spawn_async`]: Handle::spawn_async
+/// [`handle`]: crate::join::JoinHandle
+#[must_use = "futures do nothing unless polled"]
+pub struct SpawnAsync<R> {
+    handle: Handle,
+    runnable: Option<R>
+}
+
+// The runnable is never pinned.
+impl<R> Unpin for SpawnAsync<R> {}
+
+impl<R> Future for SpawnAsync<R>
+where
+    R: Runnable
+{
+    type Output = JoinHandle<R::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.handle.core.poll_reserve(cx) {
+            let runnable = self.runnable.take().expect("SpawnAsync polled after completion");
+            Poll::Ready(self.handle.spawn_reserved(runnable, Priority::Normal))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
 pub struct ContextGuard<'a>(PhantomData<&'a Handle>);
 
 impl Drop for ContextGuard<'_> {
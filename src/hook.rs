@@ -1,7 +1,9 @@
 use tiny_fn::tiny_fn;
+use crate::error::Panic;
 
 tiny_fn! {
     pub struct HookFn = Fn();
+    pub struct PanicFn = Fn(panic: &Panic);
 }
 
 /// A container for all the hooks provided to the pool.
@@ -14,5 +16,9 @@ pub struct Hooks {
     /// The function to execute after a task is executed.
     pub after_task: Option<HookFn<'static>>,
     /// The function to execute before executing a task.
-    pub before_task: Option<HookFn<'static>>
+    pub before_task: Option<HookFn<'static>>,
+    /// The function to execute when a task panics.
+    pub on_panic: Option<PanicFn<'static>>,
+    /// The function to execute when a worker thread dies and is replaced by a new one.
+    pub on_restart: Option<PanicFn<'static>>
 }
@@ -1,6 +1,9 @@
+use crate::cancel::{CancellationToken, TaskId};
 use crate::wait::Waiter;
-use crate::error::Result;
-use std::{future::Future, pin::Pin, task::{Context, Poll}};
+use crate::error::{Error, Result};
+use crate::periodic::Shared;
+use crate::timer::TimerKey;
+use std::{future::Future, pin::Pin, sync::Arc, task::{Context, Poll, Waker}, time::{Duration, Instant}};
 
 
 /// A handle used to retrieve the output of a task.
@@ -11,26 +14,192 @@ use std::{future::Future, pin::Pin, task::{Context, Poll}};
 /// [`wait`]: JoinHandle::wait
 #[must_use = "If you don't want the result, use spawn_detached."]
 pub struct JoinHandle<T> {
-    pub(crate) inner: Waiter<T>
+    pub(crate) inner: Waiter<T>,
+    pub(crate) token: CancellationToken,
+    /// The key of the task at the timer, if it was spawned with a delay.
+    pub(crate) timer: Option<TimerKey>,
+    /// The waker of the task if it is a future, so it gets polled and dropped once cancelled.
+    pub(crate) waker: Option<Waker>
 }
 
-unsafe impl<T> Send for JoinHandle<T> {}
-
 impl<T> JoinHandle<T> {
     /// Waits for the result synchronously.
     pub fn wait(mut self) -> Result<T> {
         self.inner.wait()
     }
+
+    /// Returns the id of the task.
+    pub fn id(&self) -> TaskId {
+        self.token.id()
+    }
+
+    /// Returns whether the task finished, so [`wait`] won't block.
+    ///
+    /// [`wait`]: JoinHandle::wait
+    pub fn is_finished(&self) -> bool {
+        self.inner.is_ready()
+    }
+
+    /// Returns the result if the task finished, or the handle back otherwise.
+    pub fn try_wait(mut self) -> std::result::Result<Result<T>, Self> {
+        match self.inner.try_get() {
+            Some(result) => Ok(result),
+            None => Err(self)
+        }
+    }
+
+    /// Waits for the result for at most the given duration, returning the handle back if the
+    /// task didn't finish in time.
+    pub fn wait_timeout(self, timeout: Duration) -> std::result::Result<Result<T>, Self> {
+        self.wait_deadline(Instant::now() + timeout)
+    }
+
+    /// Waits for the result until the given instant, returning the handle back if the task
+    /// didn't finish in time.
+    pub fn wait_deadline(mut self, deadline: Instant) -> std::result::Result<Result<T>, Self> {
+        match self.inner.wait_deadline(deadline) {
+            Some(result) => Ok(result),
+            None => Err(self)
+        }
+    }
+
+    /// Aborts the task.
+    ///
+    /// If the task is still queued or waiting for its delay, it won't be executed, so the handle
+    /// resolves with [`Aborted`] right away. Delayed tasks are removed from the pool at once,
+    /// while queued ones are removed lazily, keeping their slot of the [`queue capacity`] until a
+    /// worker thread pops and skips them. If the task is already running, its
+    /// [`CancellationToken`] is marked as cancelled and the handle resolves with [`Cancelled`]
+    /// once the task returns. Aborting a task that already finished does nothing.
+    ///
+    /// Futures spawned with [`spawn_future`] are dropped once cancelled, without waiting for
+    /// them to complete.
+    ///
+    /// [`Aborted`]: crate::error::Error::Aborted
+    /// [`queue capacity`]: crate::builder::WorkerPoolBuilder::queue_capacity
+    /// [`Cancelled`]: crate::error::Error::Cancelled
+    /// [`spawn_future`]: crate::handle::Handle::spawn_future
+    pub fn abort(&self) {
+        if self.token.abort() {
+            if let Some(key) = &self.timer {
+                key.remove();
+            }
+            self.inner.set(Err(Error::Aborted));
+        } else if self.token.cancel() {
+            if let Some(waker) = &self.waker {
+                waker.wake_by_ref();
+            }
+        }
+    }
 }
 
 impl<T> Future for JoinHandle<T> {
     type Output = Result<T>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        if let Some(item) = self.inner.try_get() {
-            Poll::Ready(item)
+        match self.inner.poll(cx) {
+            Some(item) => Poll::Ready(item),
+            None => Poll::Pending
+        }
+    }
+}
+
+/// A handle used to control a periodic task.
+///
+/// The handle can be cloned, and the task can be waited synchronously by using [`wait`] or the
+/// handle can be `.await`ed to wait for it asynchronously. Both resolve once the task has run
+/// the specified amount of times or when it gets cancelled.
+///
+/// Dropping the handle doesn't cancel the task.
+///
+/// [`wait`]: PeriodicHandle::wait
+#[derive(Clone)]
+pub struct PeriodicHandle {
+    pub(crate) shared: Arc<Shared>
+}
+
+impl PeriodicHandle {
+    /// Cancels the task, so it won't run again.
+    pub fn cancel(&self) {
+        self.shared.finish();
+    }
+
+    /// Pauses the task, it won't run until [`resume`] is called.
+    ///
+    /// [`resume`]: PeriodicHandle::resume
+    pub fn pause(&self) {
+        self.shared.control.lock().paused = true;
+    }
+
+    /// Resumes a paused task, the runs that were due while paused are skipped.
+    pub fn resume(&self) {
+        self.shared.control.lock().paused = false;
+    }
+
+    /// Returns whether the task is paused.
+    pub fn is_paused(&self) -> bool {
+        self.shared.control.lock().paused
+    }
+
+    /// Returns whether the task has finished, either by running out of runs or by being
+    /// cancelled.
+    pub fn is_finished(&self) -> bool {
+        self.shared.is_finished()
+    }
+
+    /// Returns the interval between runs of the task.
+    pub fn every(&self) -> Duration {
+        self.shared.control.lock().every
+    }
+
+    /// Sets the interval between runs of the task, the change takes effect after the next run.
+    pub fn set_every(&self, every: Duration) {
+        self.shared.control.lock().every = every;
+    }
+
+    /// Returns how many runs the task has left, [`None`] means it will run until cancelled.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn remaining(&self) -> Option<usize> {
+        self.shared.control.lock().times
+    }
+
+    /// Sets how many more times the task will run, [`None`] means it will run until cancelled.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn set_times(&self, times: Option<usize>) {
+        self.shared.control.lock().times = times;
+    }
+
+    /// Sets whether the task stops being rescheduled after it panics, `false` by default.
+    ///
+    /// Panics are handled by the [`panic policy`] of the pool either way.
+    ///
+    /// [`panic policy`]: crate::builder::WorkerPoolBuilder::panic_policy
+    pub fn set_stop_on_panic(&self, stop: bool) {
+        self.shared.control.lock().stop_on_panic = stop;
+    }
+
+    /// Waits synchronously until the task finishes.
+    pub fn wait(&self) {
+        let mut control = self.shared.control.lock();
+        while !control.finished {
+            self.shared.condvar.wait(&mut control);
+        }
+    }
+}
+
+impl Future for PeriodicHandle {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut control = self.shared.control.lock();
+        if control.finished {
+            Poll::Ready(())
         } else {
-            self.inner.set_waker(cx);
+            if !control.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                control.wakers.push(cx.waker().clone());
+            }
             Poll::Pending
         }
     }
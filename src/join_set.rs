@@ -0,0 +1,311 @@
+use std::collections::{HashMap, VecDeque};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use futures_core::Stream;
+use parking_lot::Mutex;
+use crate::cancel::CancellationToken;
+use crate::error::{Error, Panic, Result};
+use crate::handle::Handle;
+use crate::priority::Priority;
+use crate::runnable::Runnable;
+use crate::signal::{Blocker, Signal};
+use crate::sync::{Outcome, Task};
+
+/// The order [`JoinSet::collect`] returns the results in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Order {
+    /// The results are returned in the order the tasks were spawned.
+    #[default]
+    Submission,
+    /// The results are returned in the order the tasks finished.
+    Completion
+}
+
+/// A group of tasks spawned into the same pool, whose results can be retrieved as they finish.
+///
+/// Results can be waited synchronously by using [`next_completed`] and [`wait_all`], or the set
+/// can be used as a [`Stream`] of results. Each result comes along with the index of its task,
+/// which is the number of tasks spawned into the set before it.
+///
+/// By default, every task still pending is aborted once the set is dropped.
+///
+/// [`next_completed`]: JoinSet::next_completed
+/// [`wait_all`]: JoinSet::wait_all
+/// [`Stream`]: futures_core::Stream
+pub struct JoinSet<T> {
+    handle: Handle,
+    shared: Arc<Mutex<Shared<T>>>,
+    /// The number of tasks spawned.
+    spawned: usize,
+    /// The number of results returned.
+    returned: usize,
+    abort_on_drop: bool
+}
+
+/// The state shared between a set and its tasks.
+struct Shared<T> {
+    completed: VecDeque<(usize, Result<T>)>,
+    /// The tokens of the tasks that didn't finish yet.
+    tokens: HashMap<usize, CancellationToken>,
+    abort_on_error: bool,
+    /// The thread waiting for a result, if any.
+    waiter: Option<Arc<Signal>>,
+    waker: Option<Waker>
+}
+
+/// A task spawned into a set, it sends its result to the set once executed.
+struct SetTask<R: Runnable> {
+    runnable: R,
+    index: usize,
+    shared: Arc<Mutex<Shared<R::Output>>>
+}
+
+impl<T: Send + 'static> JoinSet<T> {
+    /// Creates an empty set whose tasks are spawned into the pool of the given handle.
+    pub fn new(handle: &Handle) -> Self {
+        Self {
+            handle: handle.clone(),
+            shared: Arc::new(Mutex::new(Shared {
+                completed: VecDeque::new(),
+                tokens: HashMap::new(),
+                abort_on_error: false,
+                waiter: None,
+                waker: None
+            })),
+            spawned: 0,
+            returned: 0,
+            abort_on_drop: true
+        }
+    }
+
+    /// Sets whether the pending tasks are aborted once the set is dropped, `true` by default.
+    pub fn abort_on_drop(mut self, abort: bool) -> Self {
+        self.abort_on_drop = abort;
+        self
+    }
+
+    /// Sets whether every pending task is aborted once a task of the set panics, `false` by
+    /// default.
+    pub fn abort_on_error(self, abort: bool) -> Self {
+        self.shared.lock().abort_on_error = abort;
+        self
+    }
+
+    /// Spawns a new task into the set, returning its index.
+    ///
+    /// When called from a worker thread, if the queue is full or the pool is shut down, the task
+    /// runs in the current thread instead.
+    pub fn spawn<R>(&mut self, runnable: R) -> usize
+    where
+        R: Runnable<Output = T>
+    {
+        let index = self.spawned;
+        let token = CancellationToken::new();
+        let task = SetTask {
+            runnable,
+            index,
+            shared: Arc::clone(&self.shared)
+        };
+        // Tasks aborted by the set are sent the error by the set itself, this is only called
+        // when the pool drops the task.
+        let shared = Arc::clone(&self.shared);
+        let abort = move |error| shared.lock().push(index, Err(error));
+
+        // Waiting for room or panicking would stall the worker, so the task runs here instead.
+        let reserved = match self.handle.core.driver.is_worker() {
+            true => self.handle.core.try_reserve().is_ok(),
+            false => {
+                self.handle.reserve();
+                true
+            }
+        };
+        self.shared.lock().tokens.insert(index, token.clone());
+        let task = Task::with_abort(move || task.run(), Some(token), abort);
+        if reserved {
+            self.handle.core.schedule(task, Priority::Normal);
+        } else {
+            task.run();
+        }
+        self.spawned += 1;
+        index
+    }
+
+    /// Returns the number of tasks whose result hasn't been returned yet.
+    pub fn len(&self) -> usize {
+        self.spawned - self.returned
+    }
+
+    /// Returns whether every result has been returned.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Aborts every pending task, as [`JoinHandle::abort`] does.
+    ///
+    /// [`JoinHandle::abort`]: crate::join::JoinHandle::abort
+    pub fn abort_all(&self) {
+        self.shared.lock().abort_all();
+    }
+
+    /// Returns the index and result of the next task that finishes, if any finished already.
+    pub fn try_next_completed(&mut self) -> Option<(usize, Result<T>)> {
+        let next = self.shared.lock().completed.pop_front();
+        self.returned += next.is_some() as usize;
+        next
+    }
+
+    /// Waits for the next task to finish, returning its index and result, or [`None`] if there
+    /// are no tasks left.
+    ///
+    /// If called from a worker thread, the thread executes queued tasks while it waits.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn next_completed(&mut self) -> Option<(usize, Result<T>)> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let blocker = Blocker::new();
+        loop {
+            {
+                let mut shared = self.shared.lock();
+                if let Some(next) = shared.completed.pop_front() {
+                    self.returned += 1;
+                    return Some(next);
+                }
+                shared.waiter = Some(Arc::clone(blocker.signal()));
+            }
+
+            blocker.wait();
+        }
+    }
+
+    /// Waits for any task to finish, returning its result, or [`None`] if there are no tasks
+    /// left.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn wait_any(&mut self) -> Option<Result<T>> {
+        self.next_completed().map(|(_, result)| result)
+    }
+
+    /// Waits for every task to finish, returning their results in the order they were spawned.
+    pub fn wait_all(self) -> Vec<Result<T>> {
+        self.collect(Order::Submission)
+    }
+
+    /// Waits for every task to finish, returning their results in the given order.
+    ///
+    /// Results already returned by [`next_completed`] are not included.
+    ///
+    /// [`next_completed`]: JoinSet::next_completed
+    pub fn collect(mut self, order: Order) -> Vec<Result<T>> {
+        let mut results = Vec::with_capacity(self.len());
+        while let Some(next) = self.next_completed() {
+            results.push(next);
+        }
+
+        if order == Order::Submission {
+            results.sort_by_key(|(index, _)| *index);
+        }
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+impl<T: Send + 'static> Stream for JoinSet<T> {
+    type Item = (usize, Result<T>);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.is_empty() {
+            return Poll::Ready(None);
+        }
+
+        let next = {
+            let mut shared = self.shared.lock();
+            let next = shared.completed.pop_front();
+            if next.is_none() {
+                shared.waker = Some(cx.waker().clone());
+            }
+            next
+        };
+
+        match next {
+            Some(next) => {
+                self.returned += 1;
+                Poll::Ready(Some(next))
+            },
+            None => Poll::Pending
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), Some(self.len()))
+    }
+}
+
+impl<T> Drop for JoinSet<T> {
+    fn drop(&mut self) {
+        if self.abort_on_drop {
+            self.shared.lock().abort_all();
+        }
+    }
+}
+
+impl<T> Shared<T> {
+    fn abort_all(&mut self) {
+        let mut aborted = Vec::new();
+        for (index, token) in &self.tokens {
+            if token.abort() {
+                aborted.push(*index);
+            } else {
+                token.cancel();
+            }
+        }
+
+        // Tasks aborted before running won't be executed, so they won't send their result.
+        for index in aborted {
+            self.push(index, Err(Error::Aborted));
+        }
+    }
+
+    fn push(&mut self, index: usize, result: Result<T>) {
+        self.tokens.remove(&index);
+        if self.abort_on_error && matches!(result, Err(Error::Panicked(_))) {
+            self.abort_all();
+        }
+
+        self.completed.push_back((index, result));
+        if let Some(waiter) = self.waiter.take() {
+            waiter.notify();
+        }
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<R: Runnable> SetTask<R> {
+    fn run(self) -> Outcome {
+        let token = CancellationToken::current();
+        let runnable = self.runnable;
+        let res = catch_unwind(AssertUnwindSafe(|| runnable.run()));
+        let outcome = if res.is_ok() { Outcome::Completed } else { Outcome::Panicked };
+        let result = match res {
+            _ if token.as_ref().map(CancellationToken::is_cancelled).unwrap_or(false) => {
+                Err(Error::Cancelled)
+            },
+            Ok(output) => Ok(output),
+            Err(payload) => Err(Error::Panicked(Panic::new(payload, token.map(|token| token.id()))))
+        };
+        let panicked = matches!(result, Err(Error::Panicked(_)));
+        if let Err(error) = &result {
+            crate::sync::report_panic(error);
+        }
+        self.shared.lock().push(self.index, result);
+        if panicked {
+            crate::sync::apply_panic_policy();
+        }
+        outcome
+    }
+}
@@ -1,15 +1,23 @@
-#![allow(unstable_name_collisions)]
-
 pub mod builder;
+pub mod cancel;
 mod context;
 mod core;
+pub mod cron;
 mod driver;
 pub mod error;
+mod future;
 pub mod handle;
 mod hook;
 pub mod join;
+pub mod join_set;
+mod par;
+mod park;
+pub mod metrics;
 mod periodic;
+pub mod priority;
 pub mod runnable;
+pub mod scope;
+mod signal;
 mod sync;
 mod timer;
 mod wait;
@@ -21,14 +29,21 @@ mod test;
 pub mod prelude {
     pub use crate::{
         builder::WorkerPoolBuilder,
+        cancel::CancellationToken,
         error::Error,
-        handle::Handle
+        handle::Handle,
+        priority::Priority
     };
 }
 
-use std::time::Duration;
-use join::JoinHandle;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use cron::CronError;
+use error::SpawnError;
+use join::{JoinHandle, PeriodicHandle};
+use priority::Priority;
 use runnable::Runnable;
+use scope::Scope;
 
 /// Spawns a new task into the pool, returning a [`handle`] that can be used to retrieve the output.
 ///
@@ -40,6 +55,34 @@ where
     context::get().spawn(runnable)
 }
 
+/// Tries to spawn a new task into the pool, returning a [`SpawnError`] with the task instead of
+/// panicking if not inside the context of a pool, or if the pool can't accept it.
+///
+/// [`SpawnError`]: crate::error::SpawnError
+pub fn try_spawn<R>(runnable: R) -> Result<JoinHandle<R::Output>, SpawnError<R>>
+where
+    R: Runnable
+{
+    match context::try_get() {
+        Some(handle) => handle.try_spawn(runnable),
+        None => Err(SpawnError::NoContext(runnable))
+    }
+}
+
+/// Like [`try_spawn`], but doesn't return a handle, as [`spawn_detached`] does.
+///
+/// [`try_spawn`]: crate::try_spawn
+/// [`spawn_detached`]: crate::spawn_detached
+pub fn try_spawn_detached<R>(runnable: R) -> Result<(), SpawnError<R>>
+where
+    R: Runnable
+{
+    match context::try_get() {
+        Some(handle) => handle.try_spawn_detached(runnable),
+        None => Err(SpawnError::NoContext(runnable))
+    }
+}
+
 /// Like [`spawn`], spawns a new task into the pool, but doesn't return a handle, so the output
 /// cannot be retrieved and the allocation needed to do so is skipped.
 ///
@@ -51,11 +94,197 @@ where
     context::get().spawn_detached(runnable)
 }
 
+/// Spawns a batch of tasks into the pool at once, returning their [`handles`] in the same order.
+///
+/// See [`Handle::spawn_batch`] for more details.
+///
+/// [`handles`]: crate::join::JoinHandle
+/// [`Handle::spawn_batch`]: crate::handle::Handle::spawn_batch
+pub fn spawn_batch<I>(runnables: I) -> Vec<JoinHandle<<I::Item as Runnable>::Output>>
+where
+    I: IntoIterator,
+    I::Item: Runnable
+{
+    context::get().spawn_batch(runnables)
+}
+
+/// Like [`spawn_batch`], but doesn't return the handles, as [`spawn_detached`] does.
+///
+/// [`spawn_batch`]: crate::spawn_batch
+/// [`spawn_detached`]: crate::spawn_detached
+pub fn spawn_detached_batch<I>(runnables: I)
+where
+    I: IntoIterator,
+    I::Item: Runnable
+{
+    context::get().spawn_detached_batch(runnables)
+}
+
+/// Like [`spawn`], but the task is executed with the given [`priority`].
+///
+/// [`spawn`]: crate::spawn
+/// [`priority`]: crate::priority::Priority
+pub fn spawn_with_priority<R>(runnable: R, priority: Priority) -> JoinHandle<R::Output>
+where
+    R: Runnable
+{
+    context::get().spawn_with_priority(runnable, priority)
+}
+
+/// Like [`spawn_detached`], but the task is executed with the given [`priority`].
+///
+/// [`spawn_detached`]: crate::spawn_detached
+/// [`priority`]: crate::priority::Priority
+pub fn spawn_detached_with_priority<R>(runnable: R, priority: Priority)
+where
+    R: Runnable
+{
+    context::get().spawn_detached_with_priority(runnable, priority)
+}
+
+/// Spawns a future into the pool, returning a [`handle`] that can be used to retrieve its output.
+///
+/// [`handle`]: crate::join::JoinHandle
+pub fn spawn_future<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static
+{
+    context::get().spawn_future(future)
+}
+
+/// Drives the future to completion on the current thread, returning its output.
+///
+/// This doesn't need to be called inside the pool context, but when called from a worker
+/// thread, the thread keeps executing queued tasks while the future isn't ready instead of
+/// blocking, so the future can wait for tasks of the same pool.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    future::block_on(future)
+}
+
+/// Creates a [`scope`] to spawn tasks that can borrow from the current stack frame, waiting for
+/// all of them to finish before returning.
+///
+/// [`scope`]: crate::handle::Handle::scope
+pub fn scope<'env, F, T>(f: F) -> T
+where
+    F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T
+{
+    context::get().scope(f)
+}
+
+/// Runs both closures potentially in parallel using the pool, returning both results.
+///
+/// See [`Handle::join`] for more details.
+///
+/// [`Handle::join`]: crate::handle::Handle::join
+pub fn join<A, B, RA, RB>(a: A, b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA + Send,
+    B: FnOnce() -> RB + Send,
+    RA: Send,
+    RB: Send
+{
+    context::get().join(a, b)
+}
+
+/// Applies the function to every item in parallel using the pool, returning the outputs in the
+/// same order as the items.
+///
+/// See [`Handle::par_map`] for more details.
+///
+/// [`Handle::par_map`]: crate::handle::Handle::par_map
+pub fn par_map<I, F, U>(items: I, f: F) -> Vec<U>
+where
+    I: IntoIterator,
+    I::Item: Send,
+    F: Fn(I::Item) -> U + Sync,
+    U: Send
+{
+    context::get().par_map(items, f)
+}
+
+/// Calls the function with every item in parallel using the pool.
+pub fn par_for_each<I, F>(items: I, f: F)
+where
+    I: IntoIterator,
+    I::Item: Send,
+    F: Fn(I::Item) + Sync
+{
+    context::get().par_for_each(items, f)
+}
+
+/// Applies the function to every chunk of `size` elements of the slice in parallel using the
+/// pool, returning the outputs in the same order as the chunks.
+pub fn par_chunks<T, F, U>(slice: &[T], size: usize, f: F) -> Vec<U>
+where
+    T: Sync,
+    F: Fn(&[T]) -> U + Sync,
+    U: Send
+{
+    context::get().par_chunks(slice, size, f)
+}
+
+/// Reduces the items in parallel using the pool and the given associative operation.
+pub fn par_reduce<I, ID, F>(items: I, identity: ID, op: F) -> I::Item
+where
+    I: IntoIterator,
+    I::Item: Send,
+    ID: Fn() -> I::Item + Sync,
+    F: Fn(I::Item, I::Item) -> I::Item + Sync
+{
+    context::get().par_reduce(items, identity, op)
+}
+
+/// Sorts the slice in parallel using the pool, the sort is stable.
+pub fn par_sort<T>(slice: &mut [T])
+where
+    T: Ord + Send
+{
+    context::get().par_sort(slice)
+}
+
+/// Spawns a new task into the pool that will be executed after the given delay, returning a
+/// [`handle`] that can be used to retrieve the output.
+///
+/// [`handle`]: crate::join::JoinHandle
+pub fn spawn_after<R>(delay: Duration, runnable: R) -> JoinHandle<R::Output>
+where
+    R: Runnable
+{
+    context::get().spawn_after(delay, runnable)
+}
+
+/// Spawns a new task into the pool that will be executed once the given instant is reached,
+/// returning a [`handle`] that can be used to retrieve the output.
+///
+/// [`handle`]: crate::join::JoinHandle
+pub fn spawn_at<R>(at: Instant, runnable: R) -> JoinHandle<R::Output>
+where
+    R: Runnable
+{
+    context::get().spawn_at(at, runnable)
+}
+
 /// Spawns a new task that will be executed periodically by the thread pool every specified time
-/// and the specified amount of times.
-pub fn spawn_periodic<T>(task: T, every: Duration, times: Option<usize>)
+/// and the specified amount of times, returning a [`handle`] that can be used to control it.
+///
+/// [`handle`]: crate::join::PeriodicHandle
+pub fn spawn_periodic<T>(task: T, every: Duration, times: Option<usize>) -> PeriodicHandle
+where
+    T: Fn() + Send + 'static
+{
+    context::get().spawn_periodic(task, every, times)
+}
+
+/// Spawns a new task that will be executed at the times given by the cron expression,
+/// returning a [`handle`] that can be used to control it, or an error if the expression is not
+/// valid.
+///
+/// [`handle`]: crate::join::PeriodicHandle
+pub fn spawn_cron<T>(expr: &str, task: T) -> Result<PeriodicHandle, CronError>
 where
     T: Fn() + Send + 'static
 {
-    context::get().spawn_periodic(task, every, times);
+    context::get().spawn_cron(expr, task)
 }
@@ -0,0 +1,171 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use parking_lot::Mutex;
+
+/// A snapshot of the state of the pool, returned by [`Handle::metrics`].
+///
+/// Tasks spawned periodically are not counted in the task totals, and tasks cancelled while
+/// running are counted as completed.
+///
+/// [`Handle::metrics`]: crate::handle::Handle::metrics
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    /// The number of tasks waiting at the queue to be executed, approximate if the pool has no
+    /// [`queue capacity`].
+    ///
+    /// [`queue capacity`]: crate::builder::WorkerPoolBuilder::queue_capacity
+    pub queue_depth: usize,
+    /// The number of periodic and delayed tasks waiting for their deadline.
+    pub pending_timers: usize,
+    /// The total number of tasks spawned.
+    pub spawned: u64,
+    /// The total number of tasks executed.
+    pub completed: u64,
+    /// The total number of tasks that panicked.
+    pub panicked: u64,
+    /// The total number of tasks dropped without being executed.
+    pub aborted: u64,
+    /// The total number of worker threads that died and were replaced.
+    pub restarts: u64,
+    /// The metrics of each worker thread alive.
+    pub workers: Vec<WorkerMetrics>,
+    /// How long the pool has been running.
+    pub uptime: Duration
+}
+
+/// The metrics of a worker thread.
+#[derive(Clone, Debug)]
+pub struct WorkerMetrics {
+    /// The name of the thread.
+    pub name: Option<String>,
+    /// The number of tasks the thread executed.
+    pub tasks: u64,
+    /// The time the thread spent executing tasks.
+    pub busy: Duration,
+    /// The time the thread spent waiting for tasks.
+    pub idle: Duration,
+    /// The number of times the thread went to sleep waiting for tasks.
+    pub parks: u64
+}
+
+/// The counters of the pool, updated as tasks are spawned and executed.
+pub(crate) struct Counters {
+    started: Instant,
+    spawned: AtomicU64,
+    completed: AtomicU64,
+    panicked: AtomicU64,
+    aborted: AtomicU64,
+    restarts: AtomicU64,
+    workers: Mutex<Vec<Arc<WorkerCounters>>>
+}
+
+impl Default for Counters {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            spawned: AtomicU64::new(0),
+            completed: AtomicU64::new(0),
+            panicked: AtomicU64::new(0),
+            aborted: AtomicU64::new(0),
+            restarts: AtomicU64::new(0),
+            workers: Mutex::default()
+        }
+    }
+}
+
+impl Counters {
+    pub fn spawned(&self) {
+        self.spawned_many(1);
+    }
+
+    pub fn spawned_many(&self, count: usize) {
+        self.spawned.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn completed(&self) {
+        self.completed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn panicked(&self) {
+        self.panicked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn aborted(&self, count: usize) {
+        self.aborted.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn restarted(&self) {
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registers the counters of a new worker thread.
+    pub fn add_worker(&self) -> Arc<WorkerCounters> {
+        let counters = Arc::new(WorkerCounters {
+            name: std::thread::current().name().map(ToString::to_string),
+            tasks: AtomicU64::new(0),
+            busy: AtomicU64::new(0),
+            idle: AtomicU64::new(0),
+            parks: AtomicU64::new(0)
+        });
+
+        self.workers.lock().push(Arc::clone(&counters));
+        counters
+    }
+
+    pub fn remove_worker(&self, counters: &Arc<WorkerCounters>) {
+        self.workers.lock().retain(|worker| !Arc::ptr_eq(worker, counters));
+    }
+
+    pub fn snapshot(&self, queue_depth: usize, pending_timers: usize) -> Metrics {
+        Metrics {
+            queue_depth,
+            pending_timers,
+            spawned: self.spawned.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+            panicked: self.panicked.load(Ordering::Relaxed),
+            aborted: self.aborted.load(Ordering::Relaxed),
+            restarts: self.restarts.load(Ordering::Relaxed),
+            workers: self.workers.lock().iter().map(|worker| worker.snapshot()).collect(),
+            uptime: self.started.elapsed()
+        }
+    }
+}
+
+/// The counters of a worker thread.
+pub(crate) struct WorkerCounters {
+    name: Option<String>,
+    tasks: AtomicU64,
+    busy: AtomicU64,
+    idle: AtomicU64,
+    parks: AtomicU64
+}
+
+impl WorkerCounters {
+    /// Records a task executed, taking the given time.
+    pub fn task(&self, busy: Duration) {
+        self.tasks.fetch_add(1, Ordering::Relaxed);
+        self.busy.fetch_add(busy.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Returns the number of tasks the thread executed.
+    pub fn tasks(&self) -> u64 {
+        self.tasks.load(Ordering::Relaxed)
+    }
+
+    /// Records the thread waiting for tasks for the given time.
+    pub fn park(&self, idle: Duration) {
+        self.parks.fetch_add(1, Ordering::Relaxed);
+        self.idle.fetch_add(idle.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> WorkerMetrics {
+        WorkerMetrics {
+            name: self.name.clone(),
+            tasks: self.tasks.load(Ordering::Relaxed),
+            busy: Duration::from_nanos(self.busy.load(Ordering::Relaxed)),
+            idle: Duration::from_nanos(self.idle.load(Ordering::Relaxed)),
+            parks: self.parks.load(Ordering::Relaxed)
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::handle::Handle;
+
+/// How many parts per thread the input is split into, so threads that finish early can take
+/// work from the busy ones.
+const PARTS_PER_THREAD: usize = 4;
+/// Parts smaller than this are sorted in place, as splitting them costs more than it saves.
+const MIN_SORT_PART: usize = 4096;
+
+/// Returns the length of the parts the input is split into, based on the number of threads of
+/// the pool.
+fn part_len(handle: &Handle, len: usize, min: usize) -> usize {
+    let parts = handle.threads().max(1) * PARTS_PER_THREAD;
+    (len / parts).max(min).max(1)
+}
+
+/// Splits the slice in halves until they are at most `part` long, running the halves in
+/// parallel and calling `f` with each of the resulting parts.
+fn split<T, F>(handle: &Handle, slice: &mut [T], part: usize, f: &F)
+where
+    T: Send,
+    F: Fn(&mut [T]) + Sync
+{
+    if slice.len() <= part {
+        f(slice);
+        return;
+    }
+
+    let (left, right) = slice.split_at_mut(slice.len() / 2);
+    handle.join(|| split(handle, left, part, f), || split(handle, right, part, f));
+}
+
+pub(crate) fn map<I, F, U>(handle: &Handle, items: I, f: F) -> Vec<U>
+where
+    I: IntoIterator,
+    I::Item: Send,
+    F: Fn(I::Item) -> U + Sync,
+    U: Send
+{
+    let mut slots = items.into_iter()
+        .map(|item| (Some(item), None))
+        .collect::<Vec<_>>();
+    let part = part_len(handle, slots.len(), 1);
+
+    split(handle, &mut slots, part, &|slots| {
+        for (item, output) in slots {
+            *output = item.take().map(&f);
+        }
+    });
+
+    slots.into_iter()
+        .map(|(_, output)| output.unwrap())
+        .collect()
+}
+
+pub(crate) fn for_each<I, F>(handle: &Handle, items: I, f: F)
+where
+    I: IntoIterator,
+    I::Item: Send,
+    F: Fn(I::Item) + Sync
+{
+    let mut slots = items.into_iter().map(Some).collect::<Vec<_>>();
+    let part = part_len(handle, slots.len(), 1);
+
+    split(handle, &mut slots, part, &|slots| {
+        slots.iter_mut().filter_map(Option::take).for_each(&f);
+    });
+}
+
+pub(crate) fn reduce<I, ID, F>(handle: &Handle, items: I, identity: ID, op: F) -> I::Item
+where
+    I: IntoIterator,
+    I::Item: Send,
+    ID: Fn() -> I::Item + Sync,
+    F: Fn(I::Item, I::Item) -> I::Item + Sync
+{
+    let mut slots = items.into_iter().map(Some).collect::<Vec<_>>();
+    let part = part_len(handle, slots.len(), 1);
+    reduce_slots(handle, &mut slots, part, &identity, &op)
+}
+
+fn reduce_slots<T, ID, F>(
+    handle: &Handle,
+    slots: &mut [Option<T>],
+    part: usize,
+    identity: &ID,
+    op: &F
+) -> T
+where
+    T: Send,
+    ID: Fn() -> T + Sync,
+    F: Fn(T, T) -> T + Sync
+{
+    if slots.len() <= part {
+        return slots.iter_mut()
+            .filter_map(Option::take)
+            .fold(identity(), op);
+    }
+
+    let (left, right) = slots.split_at_mut(slots.len() / 2);
+    let (left, right) = handle.join(
+        || reduce_slots(handle, left, part, identity, op),
+        || reduce_slots(handle, right, part, identity, op)
+    );
+    op(left, right)
+}
+
+pub(crate) fn sort_by<T, F>(handle: &Handle, slice: &mut [T], compare: &F)
+where
+    T: Send,
+    F: Fn(&T, &T) -> std::cmp::Ordering + Sync
+{
+    let part = part_len(handle, slice.len(), MIN_SORT_PART);
+    sort_parts(handle, slice, part, compare);
+}
+
+fn sort_parts<T, F>(handle: &Handle, slice: &mut [T], part: usize, compare: &F)
+where
+    T: Send,
+    F: Fn(&T, &T) -> std::cmp::Ordering + Sync
+{
+    if slice.len() > part {
+        let (left, right) = slice.split_at_mut(slice.len() / 2);
+        handle.join(
+            || sort_parts(handle, left, part, compare),
+            || sort_parts(handle, right, part, compare)
+        );
+    }
+
+    // The standard sort detects the two sorted halves as runs, so this merges them in linear
+    // time.
+    slice.sort_by(compare);
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+use std::sync::atomic::{fence, AtomicUsize, Ordering};
+use std::time::Instant;
+use crossbeam_utils::sync::{Parker, Unparker};
+use parking_lot::Mutex;
+
+thread_local! {
+    static PARK: Park = Park::new();
+}
+
+/// The parker of a thread, along with the unparker other threads use to wake it up.
+struct Park {
+    parker: Parker,
+    unparker: Arc<Unparker>
+}
+
+impl Park {
+    fn new() -> Self {
+        let parker = Parker::new();
+        let unparker = Arc::new(parker.unparker().clone());
+        Self {
+            parker,
+            unparker
+        }
+    }
+}
+
+/// Returns the unparker that wakes up the current thread from [`park`].
+pub fn unparker() -> Arc<Unparker> {
+    PARK.with(|park| Arc::clone(&park.unparker))
+}
+
+/// Puts the current thread to sleep until it is unparked or the deadline is reached, returning
+/// whether the deadline was reached.
+///
+/// An unpark that happened before calling this makes it return immediately, so a wakeup sent
+/// after registering the thread is never lost.
+pub fn park(deadline: Option<Instant>) -> bool {
+    PARK.with(|park| match deadline {
+        Some(deadline) => {
+            park.parker.park_deadline(deadline);
+            Instant::now() >= deadline
+        },
+        None => {
+            park.parker.park();
+            false
+        }
+    })
+}
+
+/// The worker threads sleeping waiting for tasks, so they can be woken up one by one.
+///
+/// A thread registers itself before checking for the last time whether there is work, and the
+/// threads pushing work check the registered ones after pushing it, both separated by a fence,
+/// so either the sleeping thread sees the work or the pushing one sees the thread.
+#[derive(Default)]
+pub struct Sleepers {
+    list: Mutex<Vec<Arc<Unparker>>>,
+    /// The length of the list, read without taking the lock to skip notifying busy pools.
+    count: AtomicUsize,
+    /// The thread sleeping until the next deadline of the timer.
+    watcher: Mutex<Option<Arc<Unparker>>>
+}
+
+impl Sleepers {
+    /// Registers the thread of the given unparker as sleeping.
+    pub fn register(&self, unparker: &Arc<Unparker>) {
+        self.list.lock().push(Arc::clone(unparker));
+        self.count.fetch_add(1, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+    }
+
+    /// Removes the thread of the given unparker, if it wasn't woken up through the list already,
+    /// returning whether it was still registered.
+    pub fn unregister(&self, unparker: &Arc<Unparker>) -> bool {
+        let mut list = self.list.lock();
+        match list.iter().position(|other| Arc::ptr_eq(other, unparker)) {
+            Some(index) => {
+                list.swap_remove(index);
+                self.count.fetch_sub(1, Ordering::SeqCst);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Makes the thread of the given unparker watch the timer, returning `false` if another
+    /// thread is watching it already.
+    pub fn watch(&self, unparker: &Arc<Unparker>) -> bool {
+        let mut watcher = self.watcher.lock();
+        if watcher.is_some() {
+            return false;
+        }
+
+        *watcher = Some(Arc::clone(unparker));
+        drop(watcher);
+        fence(Ordering::SeqCst);
+        true
+    }
+
+    /// Stops the thread of the given unparker from watching the timer, returning `false` if it
+    /// was already woken up because the timer changed.
+    pub fn unwatch(&self, unparker: &Arc<Unparker>) -> bool {
+        let mut watcher = self.watcher.lock();
+        match watcher.as_ref() {
+            Some(other) if Arc::ptr_eq(other, unparker) => {
+                *watcher = None;
+                true
+            },
+            _ => false
+        }
+    }
+
+    /// Wakes up one sleeping thread, returning `false` if there was none.
+    pub fn notify_one(&self) -> bool {
+        fence(Ordering::SeqCst);
+        if self.count.load(Ordering::SeqCst) == 0 {
+            return false;
+        }
+
+        let sleeper = {
+            let mut list = self.list.lock();
+            let sleeper = list.pop();
+            if sleeper.is_some() {
+                self.count.fetch_sub(1, Ordering::SeqCst);
+            }
+            sleeper
+        };
+
+        match sleeper {
+            Some(sleeper) => {
+                sleeper.unpark();
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Wakes up to `count` sleeping threads, returning how many were woken up.
+    pub fn notify(&self, count: usize) -> usize {
+        (0..count).take_while(|_| self.notify_one()).count()
+    }
+
+    /// Wakes up the thread watching the timer because its earliest deadline changed, or any
+    /// sleeping thread if none is watching it, so it starts doing so.
+    pub fn notify_timer(&self) {
+        fence(Ordering::SeqCst);
+        match self.watcher.lock().take() {
+            Some(watcher) => watcher.unpark(),
+            None => {
+                self.notify_one();
+            }
+        }
+    }
+
+    /// Wakes up every sleeping thread.
+    pub fn notify_all(&self) {
+        fence(Ordering::SeqCst);
+        let list = {
+            let mut list = self.list.lock();
+            self.count.fetch_sub(list.len(), Ordering::SeqCst);
+            std::mem::take(&mut *list)
+        };
+
+        list.iter().for_each(|sleeper| sleeper.unpark());
+        if let Some(watcher) = self.watcher.lock().take() {
+            watcher.unpark();
+        }
+    }
+}
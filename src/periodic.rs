@@ -1,18 +1,61 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::task::Waker;
+use std::time::{Duration, Instant, SystemTime};
+use parking_lot::{Condvar, Mutex};
 use tiny_fn::tiny_fn;
+use crate::cron::Schedule;
+use crate::error::Panic;
 use crate::handle::Handle;
 
 tiny_fn! {
     struct PeriodicFn = Fn();
 }
 
+/// The state of a periodic task that can be modified through its [`PeriodicHandle`].
+///
+/// [`PeriodicHandle`]: crate::join::PeriodicHandle
+pub struct Control {
+    pub every: Duration,
+    pub times: Option<usize>,
+    pub paused: bool,
+    pub finished: bool,
+    /// Whether the task stops being rescheduled after a panic.
+    pub stop_on_panic: bool,
+    pub wakers: Vec<Waker>
+}
+
+/// The state shared between a periodic task and its handles.
+pub struct Shared {
+    pub control: Mutex<Control>,
+    pub condvar: Condvar
+}
+
+impl Shared {
+    pub fn is_finished(&self) -> bool {
+        self.control.lock().finished
+    }
+
+    /// Marks the task as finished, waking up everyone waiting for it.
+    pub fn finish(&self) {
+        let mut control = self.control.lock();
+        if control.finished {
+            return;
+        }
+
+        control.finished = true;
+        control.wakers.drain(..).for_each(Waker::wake);
+        self.condvar.notify_all();
+    }
+}
+
 pub struct PeriodicTask {
     handle: Handle,
     fun: PeriodicFn<'static>,
-    every: Duration,
     next: Instant,
-    times: Option<usize>
+    /// The cron schedule of the task, if it doesn't run at a fixed interval.
+    cron: Option<Schedule>,
+    shared: Arc<Shared>
 }
 
 impl PeriodicTask {
@@ -24,28 +67,123 @@ impl PeriodicTask {
         Self {
             handle,
             fun: PeriodicFn::new(fun),
-            every,
             next,
-            times
+            cron: None,
+            shared: Arc::new(Shared {
+                control: Mutex::new(Control {
+                    every,
+                    times,
+                    paused: false,
+                    finished: false,
+                    stop_on_panic: false,
+                    wakers: Vec::new()
+                }),
+                condvar: Condvar::new()
+            })
         }
     }
 
+    /// Creates a task that runs at the times given by the cron schedule, returning [`None`] if
+    /// the schedule never fires.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn with_cron<F>(handle: Handle, fun: F, schedule: Schedule) -> Option<Self>
+    where
+        F: Fn() + Send + 'static
+    {
+        let next = next_cron(&schedule)?;
+        let mut task = Self::new(handle, fun, Duration::ZERO, None);
+        task.next = next;
+        task.cron = Some(schedule);
+        Some(task)
+    }
+
+    pub fn shared(&self) -> Arc<Shared> {
+        Arc::clone(&self.shared)
+    }
+
     pub fn run(mut self) {
-        self.fun.call();
-        self.times.as_mut().map(|t| *t = *t-1);
+        {
+            let mut control = self.shared.control.lock();
+            if control.finished {
+                return;
+            }
 
-        if self.times.is_none() || self.times.as_ref().map(|t| *t >= 1).unwrap() {
-            self.next = Instant::now() + self.every;
-            self.reschedule();
+            match control.times.as_mut() {
+                // The budget was lowered to zero through the handle, dropping the task finishes it.
+                Some(0) => return,
+                Some(t) => *t -= 1,
+                None => ()
+            }
         }
+
+        let panicked = match catch_unwind(AssertUnwindSafe(|| self.fun.call())) {
+            Ok(_) => false,
+            Err(payload) => {
+                self.handle.core.report_panic(&Panic::new(payload, None));
+                self.handle.core.apply_panic_policy();
+                true
+            }
+        };
+
+        let every = {
+            let control = self.shared.control.lock();
+            if control.finished || control.times == Some(0) || (panicked && control.stop_on_panic) {
+                return;
+            }
+            control.every
+        };
+
+        self.next = match &self.cron {
+            Some(schedule) => match next_cron(schedule) {
+                Some(next) => next,
+                None => return
+            },
+            None => Instant::now() + every
+        };
+        self.reschedule();
     }
 
     pub fn reschedule(self) {
-        // SAFETY: We already hold an Arc, so the pointer must be valid and safe to dereference.
-        unsafe { (&*Arc::as_ptr(&self.handle.core)).schedule_periodical(self); }
+        let core = Arc::clone(&self.handle.core);
+        core.reschedule_periodical(self);
+    }
+
+    /// Returns the instant the task should run at.
+    pub fn next(&self) -> Instant {
+        self.next
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.control.lock().paused
     }
 
-    pub fn can_run(&self) -> bool {
-        Instant::now() >= self.next
+    /// Skips the current run of a paused task, moving it to the next one, returning `false` if
+    /// there is no next run.
+    pub fn skip(&mut self) -> bool {
+        let every = self.shared.control.lock().every;
+        let next = match &self.cron {
+            Some(schedule) => next_cron(schedule),
+            None => Some(Instant::now() + every)
+        };
+
+        next.map(|next| self.next = next).is_some()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.shared.is_finished()
+    }
+}
+
+/// Computes the next instant the schedule fires at from the system clock.
+fn next_cron(schedule: &Schedule) -> Option<Instant> {
+    let now = SystemTime::now();
+    let next = schedule.next_after(now)?;
+    Some(Instant::now() + next.duration_since(now).unwrap_or_default())
+}
+
+impl Drop for PeriodicTask {
+    fn drop(&mut self) {
+        self.shared.finish();
     }
 }
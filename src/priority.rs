@@ -0,0 +1,23 @@
+/// The priority of a task, workers always pick the highest priority task available.
+///
+/// To prevent starvation, lower priorities that were skipped many times in a row while having
+/// queued tasks are served first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// For background or bulk work that can wait.
+    Low,
+    /// The priority used by default.
+    #[default]
+    Normal,
+    /// For latency sensitive work.
+    High
+}
+
+impl Priority {
+    /// The number of priority levels.
+    pub(crate) const LEVELS: usize = 3;
+
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+}
@@ -4,7 +4,7 @@
 /// [`spawn_detached`]: crate::spawn_detached
 pub trait Runnable: Send + 'static {
     /// The type of the value the task returns when executed.
-    type Output: Sized + 'static;
+    type Output: Send + 'static;
     /// The task's function body.
     fn run(self) -> Self::Output;
 }
@@ -12,7 +12,7 @@ pub trait Runnable: Send + 'static {
 impl<Fun, Ret> Runnable for Fun
 where
     Fun: FnOnce() -> Ret + Send + 'static,
-    Ret: Sized + 'static
+    Ret: Send + 'static
 {
     type Output = Ret;
 
@@ -0,0 +1,288 @@
+use std::marker::PhantomData;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use parking_lot::Mutex;
+use crate::error::{Error, Result};
+use crate::handle::Handle;
+use crate::priority::Priority;
+use crate::signal::{Blocker, Signal};
+use crate::sync::{Outcome, Task};
+
+/// A scope to spawn tasks that can borrow from the stack frame that created it, created using
+/// [`Handle::scope`].
+///
+/// [`Handle::scope`]: crate::handle::Handle::scope
+pub struct Scope<'scope, 'env: 'scope> {
+    handle: Handle,
+    data: Arc<ScopeData>,
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>
+}
+
+struct ScopeData {
+    /// The number of tasks spawned that haven't finished or been dropped yet.
+    pending: AtomicUsize,
+    /// Whether a task panicked and its handle didn't retrieve the panic.
+    panicked: AtomicBool,
+    /// Notified once the last task finishes.
+    signal: Arc<Signal>
+}
+
+/// A handle used to retrieve the output of a task spawned into a [`Scope`].
+pub struct ScopedJoinHandle<'scope, T> {
+    packet: Arc<Packet<'scope, T>>
+}
+
+/// The result of a scoped task, shared between the task and its handle.
+struct Packet<'scope, T> {
+    scope: Arc<ScopeData>,
+    state: Mutex<PacketState<T>>,
+    _marker: PhantomData<&'scope ()>
+}
+
+struct PacketState<T> {
+    result: Option<Result<T>>,
+    /// The thread waiting for the result, if any.
+    waiter: Option<Arc<Signal>>
+}
+
+/// A scoped task, it makes sure the handle resolves if the pool drops the task without
+/// running it, and lets the scope return once it finishes.
+struct ScopedTask<'scope, F, T> {
+    f: Option<F>,
+    /// Released before the scope is notified, as it may hold the last reference to the result.
+    packet: Option<Arc<Packet<'scope, T>>>,
+    scope: Arc<ScopeData>
+}
+
+/// The state of the second closure of [`join`], which may run in another thread.
+struct JoinState<F, T> {
+    f: Mutex<Option<F>>,
+    result: Mutex<Option<std::thread::Result<T>>>,
+    /// Notified once the closure has run.
+    signal: Arc<Signal>
+}
+
+pub(crate) fn scope<'env, F, T>(handle: &Handle, f: F) -> T
+where
+    F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T
+{
+    let blocker = Blocker::new();
+    let scope = Scope {
+        handle: handle.clone(),
+        data: Arc::new(ScopeData {
+            pending: AtomicUsize::new(0),
+            panicked: AtomicBool::new(false),
+            signal: Arc::clone(blocker.signal())
+        }),
+        scope: PhantomData,
+        env: PhantomData
+    };
+
+    // The tasks must finish even if the closure panics, as they may borrow from it.
+    let res = catch_unwind(AssertUnwindSafe(|| f(&scope)));
+
+    while scope.data.pending.load(Ordering::Acquire) != 0 {
+        blocker.wait();
+    }
+
+    match res {
+        Err(payload) => resume_unwind(payload),
+        Ok(_) if scope.data.panicked.load(Ordering::Acquire) => {
+            panic!("A scoped task panicked")
+        },
+        Ok(output) => output
+    }
+}
+
+pub(crate) fn join<A, B, RA, RB>(handle: &Handle, a: A, b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA + Send,
+    B: FnOnce() -> RB + Send,
+    RA: Send,
+    RB: Send
+{
+    let blocker = Blocker::new();
+    let state = Arc::new(JoinState {
+        f: Mutex::new(Some(b)),
+        result: Mutex::new(None),
+        signal: Arc::clone(blocker.signal())
+    });
+
+    // If the pool can't take the second closure, it just runs here after the first one.
+    if handle.core.try_reserve().is_ok() {
+        let task_state = Arc::clone(&state);
+        let task: Box<dyn FnOnce() + Send + '_> = Box::new(move || task_state.run());
+        // SAFETY: The closure and its output are only accessed while we wait for them, if the
+        // task outlives this call it's because we took the closure back, so it only drops empty
+        // slots.
+        let task: Box<dyn FnOnce() + Send + 'static> = unsafe { std::mem::transmute(task) };
+        handle.core.schedule(Task::new(task, None, None), Priority::Normal);
+    }
+
+    let ra = catch_unwind(AssertUnwindSafe(a));
+
+    // Nobody took the second closure yet, so run it here instead of waiting.
+    let inline = state.f.lock().take();
+    let rb = match inline {
+        Some(b) => catch_unwind(AssertUnwindSafe(b)),
+        None => loop {
+            if let Some(rb) = state.result.lock().take() {
+                break rb;
+            }
+            blocker.wait();
+        }
+    };
+
+    match (ra, rb) {
+        (Ok(ra), Ok(rb)) => (ra, rb),
+        (Err(payload), _) | (_, Err(payload)) => resume_unwind(payload)
+    }
+}
+
+impl<F, T> JoinState<F, T>
+where
+    F: FnOnce() -> T
+{
+    fn run(&self) {
+        let f = self.f.lock().take();
+        if let Some(f) = f {
+            let res = catch_unwind(AssertUnwindSafe(f));
+            *self.result.lock() = Some(res);
+            self.signal.notify();
+        }
+    }
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    /// Spawns a new task into the pool that can borrow from outside the scope, returning a
+    /// [`handle`] that can be used to retrieve the output.
+    ///
+    /// The scope waits for the task before returning even if the handle is dropped. If the task
+    /// panics and its handle doesn't retrieve the panic, the scope panics once every task
+    /// finishes. If the queue is full or the pool is shut down, the task runs in the current
+    /// thread instead.
+    ///
+    /// [`handle`]: ScopedJoinHandle
+    pub fn spawn<F, T>(&'scope self, f: F) -> ScopedJoinHandle<'scope, T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope
+    {
+        let packet = Arc::new(Packet {
+            scope: Arc::clone(&self.data),
+            state: Mutex::new(PacketState {
+                result: None,
+                waiter: None
+            }),
+            _marker: PhantomData
+        });
+        let task = ScopedTask {
+            f: Some(f),
+            packet: Some(Arc::clone(&packet)),
+            scope: Arc::clone(&self.data)
+        };
+
+        let task: Box<dyn FnOnce() -> Outcome + Send + 'scope> = Box::new(move || task.run());
+        // SAFETY: The scope doesn't return until every task has run or has been dropped, along
+        // with its closure and its reference to the result, so whatever it borrows outlives it.
+        let task: Box<dyn FnOnce() -> Outcome + Send + 'static> = unsafe {
+            std::mem::transmute(task)
+        };
+
+        self.data.pending.fetch_add(1, Ordering::AcqRel);
+        match self.handle.core.try_reserve() {
+            Ok(()) => {
+                // Dropping the task resolves its handle, so there is nothing else to abort.
+                let task = Task::from_fn(task, |_| ());
+                self.handle.core.schedule(task, Priority::Normal);
+            },
+            Err(_) => {
+                task();
+            }
+        }
+
+        ScopedJoinHandle {
+            packet
+        }
+    }
+}
+
+impl<T> ScopedJoinHandle<'_, T> {
+    /// Returns whether the task has finished.
+    pub fn is_finished(&self) -> bool {
+        self.packet.state.lock().result.is_some()
+    }
+
+    /// Waits for the result of the task.
+    pub fn join(self) -> Result<T> {
+        let blocker = Blocker::new();
+
+        loop {
+            {
+                let mut state = self.packet.state.lock();
+                if let Some(result) = state.result.take() {
+                    return result;
+                }
+                state.waiter = Some(Arc::clone(blocker.signal()));
+            }
+
+            blocker.wait();
+        }
+    }
+}
+
+impl<T> Packet<'_, T> {
+    fn set(&self, result: Result<T>) {
+        let mut state = self.state.lock();
+        if state.result.is_some() {
+            return;
+        }
+
+        state.result = Some(result);
+        if let Some(waiter) = state.waiter.take() {
+            waiter.notify();
+        }
+    }
+}
+
+impl<T> Drop for Packet<'_, T> {
+    fn drop(&mut self) {
+        // The output may borrow from the scope, so it must be dropped before the scope returns.
+        if let Some(Err(Error::Panicked(_))) = self.state.get_mut().result.take() {
+            self.scope.panicked.store(true, Ordering::Release);
+        }
+    }
+}
+
+impl<F, T> ScopedTask<'_, F, T>
+where
+    F: FnOnce() -> T
+{
+    fn run(mut self) -> Outcome {
+        match (self.f.take(), &self.packet) {
+            (Some(f), Some(packet)) => {
+                let res = catch_unwind(AssertUnwindSafe(f)).map_err(Error::from);
+                let outcome = if res.is_ok() { Outcome::Completed } else { Outcome::Panicked };
+                packet.set(res);
+                outcome
+            },
+            _ => Outcome::Aborted
+        }
+    }
+}
+
+impl<F, T> Drop for ScopedTask<'_, F, T> {
+    fn drop(&mut self) {
+        // The closure may borrow from the scope, so it must be dropped before the packet.
+        self.f.take();
+        if let Some(packet) = self.packet.take() {
+            packet.set(Err(Error::Aborted));
+        }
+
+        if self.scope.pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.scope.signal.notify();
+        }
+    }
+}
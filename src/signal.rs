@@ -0,0 +1,73 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::Wake;
+use crossbeam_utils::sync::Unparker;
+use crate::core::Core;
+
+/// Wakes up the thread waiting on a [`Blocker`], it can also be used as a waker.
+pub struct Signal {
+    notified: AtomicBool,
+    unparker: Arc<Unparker>
+}
+
+impl Signal {
+    pub fn notify(&self) {
+        self.notified.store(true, Ordering::Release);
+        self.unparker.unpark();
+    }
+
+    fn is_notified(&self) -> bool {
+        self.notified.load(Ordering::Acquire)
+    }
+}
+
+impl Wake for Signal {
+    fn wake(self: Arc<Self>) {
+        self.notify();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.notify();
+    }
+}
+
+/// Blocks the current thread until its [`Signal`] is notified.
+///
+/// If the current thread is a worker, it keeps executing queued tasks while it waits, so it can
+/// wait for tasks of its own pool without deadlocking it.
+pub struct Blocker {
+    signal: Arc<Signal>,
+    core: Option<Arc<Core>>
+}
+
+impl Blocker {
+    pub fn new() -> Self {
+        let core = crate::context::try_get()
+            .map(|handle| handle.core)
+            .filter(|core| core.driver.is_worker());
+
+        Self {
+            signal: Arc::new(Signal {
+                notified: AtomicBool::new(false),
+                unparker: crate::park::unparker()
+            }),
+            core
+        }
+    }
+
+    pub fn signal(&self) -> &Arc<Signal> {
+        &self.signal
+    }
+
+    /// Waits until the signal is notified, resetting it afterwards.
+    pub fn wait(&self) {
+        match &self.core {
+            Some(core) => crate::worker::help(core, || self.signal.is_notified()),
+            None => while !self.signal.is_notified() {
+                crate::park::park(None);
+            }
+        }
+
+        self.signal.notified.store(false, Ordering::Release);
+    }
+}
@@ -1,55 +1,171 @@
 use tiny_fn::tiny_fn;
-use crate::wait::Inner;
-use crate::error::{Error, Result};
+use crate::cancel::CancellationToken;
+use crate::wait::Sender;
+use crate::error::{Error, Panic};
 use std::panic::{catch_unwind, AssertUnwindSafe};
 use crate::Runnable;
 
 tiny_fn! {
-    struct TaskFun = FnOnce();
+    struct TaskFun = FnOnce() -> Outcome;
+    struct AbortFun = FnOnce(error: Error);
+}
+
+/// How the execution of a task ended.
+pub enum Outcome {
+    Completed,
+    Panicked,
+    /// The task was aborted before running, so it was skipped.
+    Aborted,
+    /// The task is a future that isn't ready yet, it will be scheduled again once woken.
+    Pending
 }
 
 pub struct Task {
-    fun: TaskFun<'static>,
-    inner: Option<*mut Inner<()>>,
-    abort: fn(*mut Inner<()>)
+    /// Taken once the task runs.
+    fun: Option<TaskFun<'static>>,
+    token: Option<CancellationToken>,
+    /// Called when the task is aborted or dropped without running, with [`Aborted`] in the
+    /// latter case, like when the worker that popped it dies.
+    ///
+    /// [`Aborted`]: crate::error::Error::Aborted
+    abort: Option<AbortFun<'static>>
 }
 
 impl Task {
-    pub fn new<R>(fun: R, ptr: Option<*mut Inner<R::Output>>) -> Self
+    pub fn new<R>(fun: R, sender: Option<Sender<R::Output>>, token: Option<CancellationToken>) -> Self
     where
         R: Runnable
     {
+        let task_token = token.clone();
+        let abort = sender.clone().map(|sender| AbortFun::new(move |error| sender.set(Err(error))));
         Self {
-            fun: TaskFun::new(move || {
-                let res = catch_unwind(AssertUnwindSafe(|| fun.run()))
-                    .map_err(Into::into);
-                ptr.map(move |ptr| unsafe {
-                    set_result(ptr, res);
-                });
-            }),
-            inner: ptr.map(|inner| inner as *mut Inner<()>),
-            abort: abort_task::<R::Output>
+            fun: Some(TaskFun::new(move || {
+                let _guard = match &task_token {
+                    Some(token) if !token.start() => return Outcome::Aborted,
+                    Some(token) => Some(token.enter()),
+                    None => None
+                };
+
+                let id = task_token.as_ref().map(CancellationToken::id);
+                let mut res = catch_unwind(AssertUnwindSafe(|| fun.run()))
+                    .map_err(|payload| Error::Panicked(Panic::new(payload, id)));
+
+                let outcome = match &res {
+                    Ok(_) => Outcome::Completed,
+                    Err(error) => {
+                        report_panic(error);
+                        Outcome::Panicked
+                    }
+                };
+
+                if task_token.as_ref().map(|token| token.finish()).unwrap_or(false) {
+                    res = Err(Error::Cancelled);
+                }
+
+                if let Some(sender) = sender {
+                    sender.set(res);
+                }
+                if let Outcome::Panicked = outcome {
+                    apply_panic_policy();
+                }
+                outcome
+            })),
+            token,
+            abort
         }
     }
 
-    pub fn abort(mut self) {
-        self.inner.take().map(|ptr| (self.abort)(ptr));
+    /// Like [`from_fn`], but the task goes through the token as the ones created with [`new`],
+    /// so it can be aborted and cancelled.
+    ///
+    /// [`from_fn`]: Task::from_fn
+    /// [`new`]: Task::new
+    pub fn with_abort<F, A>(fun: F, token: Option<CancellationToken>, abort: A) -> Self
+    where
+        F: FnOnce() -> Outcome + Send + 'static,
+        A: FnOnce(Error) + Send + 'static
+    {
+        let task_token = token.clone();
+        Self {
+            fun: Some(TaskFun::new(move || {
+                let _guard = match &task_token {
+                    Some(token) if !token.start() => return Outcome::Aborted,
+                    Some(token) => Some(token.enter()),
+                    None => None
+                };
+
+                let outcome = fun();
+                if let Some(token) = &task_token {
+                    token.finish();
+                }
+                outcome
+            })),
+            token,
+            abort: Some(AbortFun::new(abort))
+        }
     }
 
-    pub fn run(self) {
-        self.fun.call();
+    /// Creates a task from a function that reports its own outcome, calling `abort` instead if
+    /// the task is dropped without running.
+    pub fn from_fn<F, A>(fun: F, abort: A) -> Self
+    where
+        F: FnOnce() -> Outcome + Send + 'static,
+        A: FnOnce(Error) + Send + 'static
+    {
+        Self {
+            fun: Some(TaskFun::new(fun)),
+            token: None,
+            abort: Some(AbortFun::new(abort))
+        }
+    }
+
+    /// Drops the task without running it, resolving its handle with the given error.
+    pub fn abort(mut self, error: Error) {
+        self.abort_with(error);
+    }
+
+    fn abort_with(&mut self, error: Error) {
+        let abort = self.abort.take();
+        if let Some(token) = self.token.take() {
+            // The handle already aborted the task and set the result.
+            if !token.abort() {
+                return;
+            }
+        }
+
+        if let Some(abort) = abort {
+            abort.call(error);
+        }
+    }
+
+    pub fn run(mut self) -> Outcome {
+        // Once running, the task resolves its handle by itself.
+        self.abort = None;
+        self.token = None;
+        match self.fun.take() {
+            Some(fun) => fun.call(),
+            None => Outcome::Aborted
+        }
     }
 }
 
-unsafe fn set_result<T>(ptr: *mut Inner<T>, result: Result<T>) {
-    if ptr.is_null() {
-        return;
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.abort_with(Error::Aborted);
     }
-    let inner = &mut *ptr;
-    inner.data = Some(result);
-    inner.notifier.take().map(|notifier| notifier.notify());
 }
 
-fn abort_task<T>(ptr: *mut Inner<()>) {
-    unsafe { set_result(ptr as *mut Inner<T>, Err(Error::Aborted)); }
+/// Reports the panic of a task to the pool of the current thread.
+pub(crate) fn report_panic(error: &Error) {
+    if let (Some(handle), Some(panic)) = (crate::context::try_get(), error.panic()) {
+        handle.core.report_panic(panic);
+    }
+}
+
+/// Applies the panic policy of the pool of the current thread, once the handle of the task that
+/// panicked has its result, as the policy may shut down the pool.
+pub(crate) fn apply_panic_policy() {
+    if let Some(handle) = crate::context::try_get() {
+        handle.core.apply_panic_policy();
+    }
 }
@@ -1,5 +1,7 @@
 use crate::builder::WorkerPoolBuilder;
 use super::*;
+use crate::error::Error;
+use crate::handle::Handle;
 
 #[test]
 fn hello_world() {
@@ -141,3 +143,1037 @@ fn drop_context_guard() {
         crate::spawn_detached(|| {});
     }).join().unwrap();
 }
+
+#[test]
+fn abort_queued() {
+    let handle = WorkerPoolBuilder::new()
+        .threads(1).build().unwrap();
+
+    handle.spawn_detached(|| {
+        std::thread::sleep(std::time::Duration::from_millis(500));
+    });
+
+    let join = handle.spawn(|| {
+        unreachable!("Aborted before running");
+    });
+
+    join.abort();
+    assert!(matches!(join.wait(), Err(Error::Aborted)));
+}
+
+#[test]
+fn cancel_running() {
+    use crate::cancel::CancellationToken;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(1).build().unwrap();
+
+    let join = handle.spawn(|| {
+        let token = CancellationToken::current().unwrap();
+        while !token.is_cancelled() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        1
+    });
+
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    join.abort();
+    assert!(matches!(join.wait(), Err(Error::Cancelled)));
+}
+
+#[test]
+fn periodic_handle() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let handle = WorkerPoolBuilder::new()
+        .build_owned().unwrap();
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&runs);
+    let periodic = handle.spawn_periodic(move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+    }, Duration::from_millis(50), Some(3));
+
+    assert_eq!(periodic.remaining(), Some(3));
+    periodic.wait();
+    assert!(periodic.is_finished());
+    assert_eq!(periodic.remaining(), Some(0));
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn periodic_cancel() {
+    use std::time::Duration;
+
+    let handle = WorkerPoolBuilder::new()
+        .build_owned().unwrap();
+
+    let periodic = handle.spawn_periodic(|| {}, Duration::from_millis(50), None);
+    periodic.pause();
+    assert!(periodic.is_paused());
+    periodic.set_every(Duration::from_millis(20));
+    periodic.resume();
+
+    let cancel = periodic.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(200));
+        cancel.cancel();
+    });
+
+    periodic.await;
+}
+
+#[test]
+fn priority() {
+    use crate::priority::Priority;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(1).build().unwrap();
+
+    handle.spawn_detached(|| {
+        std::thread::sleep(std::time::Duration::from_millis(300));
+    });
+
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let joins = [Priority::Low, Priority::Normal, Priority::High].into_iter()
+        .map(|priority| {
+            let order = Arc::clone(&order);
+            handle.spawn_with_priority(move || order.lock().push(priority), priority)
+        })
+        .collect::<Vec<_>>();
+
+    joins.into_iter().for_each(|join| join.wait().unwrap());
+    assert_eq!(*order.lock(), [Priority::High, Priority::Normal, Priority::Low]);
+}
+
+#[test]
+fn work_stealing() {
+    use std::collections::HashSet;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(4).build().unwrap();
+
+    // Tasks spawned from a worker go to its local queue, so the rest of workers must steal them.
+    let joins = handle.spawn(|| {
+        (0..8).map(|_| crate::spawn(|| {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            std::thread::current().id()
+        })).collect::<Vec<_>>()
+    }).wait().unwrap();
+
+    let threads = joins.into_iter()
+        .map(|join| join.wait().unwrap())
+        .collect::<HashSet<_>>();
+
+    assert!(threads.len() > 1);
+}
+
+#[test]
+fn dynamic_threads() {
+    use std::time::Duration;
+
+    let handle = WorkerPoolBuilder::new()
+        .min_threads(1)
+        .max_threads(4)
+        .keep_alive(Duration::from_millis(200))
+        .build().unwrap();
+
+    assert_eq!(handle.threads(), 1);
+
+    let joins = (0..4).map(|_| handle.spawn(|| {
+        std::thread::sleep(Duration::from_millis(200));
+    })).collect::<Vec<_>>();
+
+    let grown = handle.threads();
+    joins.into_iter().for_each(|join| join.wait().unwrap());
+    assert!(grown > 1);
+
+    // The extra threads retire once idle for the keep alive time.
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(handle.threads(), 1);
+}
+
+#[test]
+fn set_threads() {
+    use std::time::Duration;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(2).build().unwrap();
+
+    handle.set_threads(4).unwrap();
+    assert_eq!(handle.threads(), 4);
+
+    handle.set_threads(1).unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(handle.threads(), 1);
+    assert_eq!(handle.spawn(|| 1).wait().unwrap(), 1);
+}
+
+#[test]
+fn shutdown_graceful() {
+    use std::time::Duration;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(1).build().unwrap();
+
+    let joins = (0..3).map(|i| handle.spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        i
+    })).collect::<Vec<_>>();
+
+    handle.shutdown_graceful();
+
+    for (i, join) in joins.into_iter().enumerate() {
+        assert_eq!(join.wait().unwrap(), i);
+    }
+}
+
+#[test]
+fn shutdown_timeout() {
+    use std::time::Duration;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(1).build().unwrap();
+
+    let first = handle.spawn(|| std::thread::sleep(Duration::from_millis(300)));
+    let second = handle.spawn(|| unreachable!("Aborted after the timeout"));
+
+    assert!(!handle.shutdown_timeout(Duration::from_millis(50)));
+    first.wait().unwrap();
+    assert!(matches!(second.wait(), Err(Error::TimedOut)));
+}
+
+#[test]
+fn shutdown_inside() {
+    let handle = WorkerPoolBuilder::new()
+        .threads(2).build().unwrap();
+
+    handle.spawn(|| Handle::current().shutdown()).wait().unwrap();
+}
+
+#[test]
+fn bounded_queue() {
+    use crate::builder::Backpressure;
+    use std::time::Duration;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(1)
+        .queue_capacity(1)
+        .backpressure(Backpressure::Fail)
+        .build().unwrap();
+
+    let first = handle.spawn(|| std::thread::sleep(Duration::from_millis(200)));
+    // Let the worker pick the first task, so the queue is empty again.
+    std::thread::sleep(Duration::from_millis(50));
+    let second = handle.try_spawn(|| 2).unwrap();
+    let Err(third) = handle.try_spawn(|| 3) else {
+        panic!("The queue should be full");
+    };
+
+    assert_eq!(third.into_inner()(), 3);
+    first.wait().unwrap();
+    assert_eq!(second.wait().unwrap(), 2);
+}
+
+#[test]
+fn blocking_spawn() {
+    use std::time::{Duration, Instant};
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(1)
+        .queue_capacity(1)
+        .build().unwrap();
+
+    let start = Instant::now();
+    let joins = (0..3).map(|_| handle.spawn(|| {
+        std::thread::sleep(Duration::from_millis(100));
+    })).collect::<Vec<_>>();
+
+    // The third spawn must wait until the worker picks the second task.
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    joins.into_iter().for_each(|join| join.wait().unwrap());
+}
+
+#[tokio::test]
+async fn spawn_async() {
+    let handle = WorkerPoolBuilder::new()
+        .threads(1)
+        .queue_capacity(1)
+        .build().unwrap();
+
+    let joins = [
+        handle.spawn_async(|| std::thread::sleep(std::time::Duration::from_millis(50))).await,
+        handle.spawn_async(|| std::thread::sleep(std::time::Duration::from_millis(50))).await,
+        handle.spawn_async(|| std::thread::sleep(std::time::Duration::from_millis(50))).await
+    ];
+
+    for join in joins {
+        join.await.unwrap();
+    }
+}
+
+#[test]
+fn spawn_after() {
+    use std::time::{Duration, Instant};
+
+    let handle = WorkerPoolBuilder::new()
+        .build_owned().unwrap();
+
+    let start = Instant::now();
+    let join = handle.spawn_after(Duration::from_millis(100), move || start.elapsed());
+
+    assert!(join.wait().unwrap() >= Duration::from_millis(100));
+}
+
+#[test]
+fn abort_delayed() {
+    use std::time::{Duration, Instant};
+
+    let handle = WorkerPoolBuilder::new()
+        .build_owned().unwrap();
+
+    let join = handle.spawn_at(Instant::now() + Duration::from_secs(1), || {
+        unreachable!("Aborted before the deadline");
+    });
+
+    join.abort();
+    assert!(handle.core.timer.lock().is_empty());
+    assert!(matches!(join.wait(), Err(Error::Aborted)));
+}
+
+#[test]
+fn cron_schedule() {
+    use crate::cron::{CronError, Schedule};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+
+    // Friday 2024-01-05 03:00 to Monday 2024-01-08 02:30.
+    let weekdays = Schedule::parse("30 2 * * MON-FRI").unwrap();
+    assert_eq!(weekdays.next_after(at(1704423600)), Some(at(1704681000)));
+
+    // 2024-01-05 03:02:10 to 03:05:00.
+    let every_five = Schedule::parse("*/5 * * * *").unwrap();
+    assert_eq!(every_five.next_after(at(1704423730)), Some(at(1704423900)));
+
+    // 2024-03-01 to the next leap day, 2028-02-29.
+    let leap = Schedule::parse("0 0 0 29 2 *").unwrap();
+    assert_eq!(leap.next_after(at(1709251200)), Some(at(1835395200)));
+
+    assert_eq!(Schedule::parse("0 0 30 2 *").unwrap().next_after(at(0)), None);
+    assert_eq!(Schedule::parse("* * *"), Err(CronError::FieldCount(3)));
+    assert!(matches!(Schedule::parse("61 * * * *"), Err(CronError::OutOfRange { value: 61, .. })));
+    assert!(matches!(Schedule::parse("* * * FOO *"), Err(CronError::InvalidValue { .. })));
+}
+
+#[test]
+fn spawn_cron() {
+    use crate::cron::CronError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let handle = WorkerPoolBuilder::new()
+        .build_owned().unwrap();
+
+    assert!(matches!(handle.spawn_cron("0 0 30 2 *", || {}), Err(CronError::Unsatisfiable)));
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&runs);
+    let cron = handle.spawn_cron("* * * * * *", move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+    }).unwrap();
+
+    cron.set_times(Some(2));
+    cron.wait();
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn timer_precision() {
+    use std::time::{Duration, Instant};
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(2).build().unwrap();
+
+    // Wait for the workers to go to sleep.
+    std::thread::sleep(Duration::from_millis(50));
+
+    let start = Instant::now();
+    let late = handle.spawn_after(Duration::from_millis(200), Instant::now);
+    let early = handle.spawn_after(Duration::from_millis(30), Instant::now);
+
+    let early = early.wait().unwrap().duration_since(start);
+    let late = late.wait().unwrap().duration_since(start);
+
+    assert!(early >= Duration::from_millis(30) && early < Duration::from_millis(130));
+    assert!(late >= Duration::from_millis(200) && late < Duration::from_millis(300));
+}
+
+#[test]
+fn metrics() {
+    use std::time::Duration;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(2).build().unwrap();
+
+    let ok = handle.spawn(|| std::thread::sleep(Duration::from_millis(20)));
+    let panicked = handle.spawn(|| panic!("metrics"));
+    let delayed = handle.spawn_after(Duration::from_secs(10), || ());
+
+    assert_eq!(handle.metrics().pending_timers, 1);
+    delayed.abort();
+    ok.wait().unwrap();
+    assert!(panicked.wait().is_err());
+
+    // The counters are updated once the task returns, right after its handle is notified.
+    std::thread::sleep(Duration::from_millis(50));
+    let metrics = handle.metrics();
+
+    assert_eq!(metrics.spawned, 3);
+    assert_eq!(metrics.completed, 1);
+    assert_eq!(metrics.panicked, 1);
+    assert_eq!(metrics.aborted, 1);
+    assert_eq!(metrics.queue_depth, 0);
+    assert_eq!(metrics.pending_timers, 0);
+    assert_eq!(metrics.workers.len(), 2);
+    assert_eq!(metrics.workers.iter().map(|worker| worker.tasks).sum::<u64>(), 2);
+    assert!(metrics.workers.iter().any(|worker| worker.busy >= Duration::from_millis(20)));
+}
+
+#[test]
+fn spawn_future() {
+    use std::time::Duration;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(2).build().unwrap();
+
+    let inner = handle.clone();
+    let join = handle.spawn_future(async move {
+        let first = inner.spawn_after(Duration::from_millis(20), || 1).await.unwrap();
+        let second = inner.spawn(|| 2).await.unwrap();
+        first + second
+    });
+    assert_eq!(join.wait().unwrap(), 3);
+
+    let pending = handle.spawn_future(std::future::pending::<()>());
+    std::thread::sleep(Duration::from_millis(50));
+    pending.abort();
+    assert!(matches!(pending.wait(), Err(Error::Cancelled)));
+}
+
+#[test]
+fn block_on() {
+    use std::time::Duration;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(1).build().unwrap();
+
+    assert_eq!(crate::block_on(handle.spawn(|| 1)).unwrap(), 1);
+
+    // The only worker waits for tasks queued behind it, so it must run them itself.
+    let inner = handle.clone();
+    let join = handle.spawn(move || {
+        let now = inner.spawn(|| 2);
+        let later = inner.spawn_after(Duration::from_millis(20), || 3);
+        crate::block_on(async { now.await.unwrap() + later.await.unwrap() })
+    });
+    assert_eq!(crate::block_on(join).unwrap(), 5);
+}
+
+#[test]
+fn scope() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(2).build().unwrap();
+
+    let mut values = vec![1, 2, 3, 4];
+    let total = AtomicUsize::new(0);
+
+    let first = handle.scope(|s| {
+        let (left, right) = values.split_at_mut(2);
+        s.spawn(|| left.iter_mut().for_each(|value| *value *= 10));
+        s.spawn(|| right.iter_mut().for_each(|value| *value *= 10));
+        s.spawn(|| total.fetch_add(1, Ordering::SeqCst));
+        s.spawn(|| 42).join().unwrap()
+    });
+
+    assert_eq!(first, 42);
+    assert_eq!(values, [10, 20, 30, 40]);
+    assert_eq!(total.load(Ordering::SeqCst), 1);
+
+    let joined = handle.scope(|s| s.spawn(|| panic!("joined")).join());
+    assert!(matches!(joined, Err(Error::Panicked(_))));
+
+    let unjoined = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| handle.scope(|s| {
+        s.spawn(|| panic!("unjoined"));
+    })));
+    assert!(unjoined.is_err());
+
+    // Forgetting a handle leaks the result, but the scope still waits only for the task.
+    let forgotten = handle.scope(|s| {
+        std::mem::forget(s.spawn(|| total.fetch_add(1, Ordering::SeqCst)));
+        5
+    });
+    assert_eq!(forgotten, 5);
+    assert_eq!(total.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn join() {
+    fn sum(handle: &Handle, values: &[u64]) -> u64 {
+        if values.len() <= 16 {
+            return values.iter().sum();
+        }
+
+        let (left, right) = values.split_at(values.len() / 2);
+        let (left, right) = handle.join(|| sum(handle, left), || sum(handle, right));
+        left + right
+    }
+
+    // A single worker must not deadlock while both halves wait for each other.
+    let handle = WorkerPoolBuilder::new()
+        .threads(1).build().unwrap();
+
+    let values = (0..10_000).collect::<Vec<u64>>();
+    let inner = handle.clone();
+    let total = handle.spawn(move || sum(&inner, &values)).wait().unwrap();
+    assert_eq!(total, (0..10_000).sum::<u64>());
+
+    let (a, b) = handle.join(|| 1, || 2);
+    assert_eq!((a, b), (1, 2));
+}
+
+#[test]
+fn parallel_helpers() {
+    let handle = WorkerPoolBuilder::new()
+        .threads(4).build().unwrap();
+
+    let squares = handle.par_map(0..1000u64, |x| x * x);
+    assert_eq!(squares, (0..1000u64).map(|x| x * x).collect::<Vec<_>>());
+
+    let words = ["a", "bb", "ccc"];
+    assert_eq!(handle.par_map(&words, |word| word.len()), [1, 2, 3]);
+
+    let sum = std::sync::atomic::AtomicU64::new(0);
+    handle.par_for_each(squares.iter(), |x| {
+        sum.fetch_add(*x, std::sync::atomic::Ordering::Relaxed);
+    });
+    assert_eq!(sum.into_inner(), squares.iter().sum::<u64>());
+
+    let chunks = handle.par_chunks(&squares, 300, |chunk| chunk.len());
+    assert_eq!(chunks, [300, 300, 300, 100]);
+
+    let joined = handle.par_reduce((0..100).map(|x| x.to_string()), String::new, |a, b| a + &b);
+    assert_eq!(joined, (0..100).map(|x| x.to_string()).collect::<String>());
+
+    let mut values = (0..100_000u64).map(|x| x * 7919 % 100_003).collect::<Vec<_>>();
+    let mut expected = values.clone();
+    expected.sort();
+    handle.par_sort(&mut values);
+    assert_eq!(values, expected);
+}
+
+#[test]
+fn join_set() {
+    use std::time::Duration;
+    use crate::join_set::Order;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(2).build().unwrap();
+
+    let mut set = handle.join_set();
+    for i in 0..4u64 {
+        set.spawn(move || {
+            std::thread::sleep(Duration::from_millis(40 - i * 10));
+            i
+        });
+    }
+    let (index, first) = set.next_completed().unwrap();
+    assert_eq!(first.unwrap(), index as u64);
+    assert_eq!(set.len(), 3);
+    let rest = set.collect(Order::Submission).into_iter().map(|r| r.unwrap()).collect::<Vec<_>>();
+    assert_eq!(rest.len(), 3);
+    assert!(rest.windows(2).all(|w| w[0] < w[1]));
+
+    // The panic aborts the tasks still queued behind the sleeping ones.
+    let mut set = handle.join_set().abort_on_error(true);
+    set.spawn(|| std::thread::sleep(Duration::from_millis(50)));
+    set.spawn(|| {
+        std::thread::sleep(Duration::from_millis(10));
+        panic!("join set")
+    });
+    for _ in 0..4 {
+        set.spawn(|| std::thread::sleep(Duration::from_millis(50)));
+    }
+    let results = set.wait_all();
+    assert!(matches!(results[1], Err(Error::Panicked(_))));
+    assert!(results[2..].iter().any(|result| matches!(result, Err(Error::Aborted))));
+}
+
+#[tokio::test]
+async fn join_set_stream() {
+    use std::future::poll_fn;
+    use std::pin::Pin;
+    use futures_core::Stream;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(2).build().unwrap();
+
+    let mut set = handle.join_set();
+    (0..10).for_each(|i| {
+        set.spawn(move || i * 2);
+    });
+
+    let mut total = 0;
+    while let Some((index, result)) = poll_fn(|cx| Pin::new(&mut set).poll_next(cx)).await {
+        assert_eq!(result.unwrap(), index * 2);
+        total += 1;
+    }
+    assert_eq!(total, 10);
+}
+
+#[test]
+fn join_handle_wait() {
+    use std::time::{Duration, Instant};
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(1).build().unwrap();
+
+    let join = handle.spawn(|| {
+        std::thread::sleep(Duration::from_millis(100));
+        1
+    });
+    assert!(!join.is_finished());
+
+    let Err(join) = join.try_wait() else {
+        panic!("The task should still be running");
+    };
+    let start = Instant::now();
+    let Err(join) = join.wait_timeout(Duration::from_millis(20)) else {
+        panic!("The task should still be running");
+    };
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    let Ok(result) = join.wait_deadline(Instant::now() + Duration::from_secs(5)) else {
+        panic!("The task should have finished");
+    };
+    assert_eq!(result.unwrap(), 1);
+
+    let join = handle.spawn(|| 2);
+    while !join.is_finished() {
+        std::thread::yield_now();
+    }
+    assert_eq!(join.try_wait().ok().unwrap().unwrap(), 2);
+}
+
+#[test]
+fn error_details() {
+    let handle = WorkerPoolBuilder::new()
+        .threads(1).build().unwrap();
+
+    let join = handle.spawn(|| panic!("boom {}", 1));
+    let id = join.id();
+    let error = join.wait().unwrap_err();
+
+    assert!(error.is_panic());
+    assert_eq!(error.panic_message(), Some("boom 1"));
+    let panic = error.panic().unwrap();
+    assert_eq!(panic.task_id(), Some(id));
+    assert_eq!(panic.thread_name(), Some("Worker-Pool worker"));
+    assert_eq!(
+        error.to_string(),
+        format!("task {} panicked on thread 'Worker-Pool worker': boom 1", id)
+    );
+
+    let error: Box<dyn std::error::Error> = Box::new(Error::PoolShutdown);
+    assert_eq!(error.to_string(), "task aborted, the pool was shut down");
+
+    let join = handle.spawn(|| std::thread::sleep(std::time::Duration::from_millis(50)));
+    let queued = handle.spawn(|| ());
+    // Let the worker pick the first task.
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    handle.shutdown();
+    join.wait().unwrap();
+    assert!(matches!(queued.wait(), Err(Error::PoolShutdown)));
+}
+
+#[test]
+fn panic_policy() {
+    use crate::builder::PanicPolicy;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let panics = Arc::new(AtomicUsize::new(0));
+    let cloned = Arc::clone(&panics);
+    let handle = WorkerPoolBuilder::new()
+        .threads(1)
+        .on_panic(move |panic| {
+            assert_eq!(panic.message(), Some("periodic"));
+            cloned.fetch_add(1, Ordering::SeqCst);
+        })
+        .build().unwrap();
+
+    // The worker survives the panics, so the task keeps being rescheduled.
+    let periodic = handle.spawn_periodic(|| panic!("periodic"), Duration::from_millis(5), Some(3));
+    periodic.wait();
+    assert_eq!(panics.load(Ordering::SeqCst), 3);
+    assert_eq!(handle.spawn(|| 1).wait().unwrap(), 1);
+
+    let periodic = handle.spawn_periodic(|| panic!("periodic"), Duration::from_millis(5), None);
+    periodic.set_stop_on_panic(true);
+    periodic.wait();
+    assert_eq!(panics.load(Ordering::SeqCst), 4);
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(1)
+        .panic_policy(PanicPolicy::Shutdown)
+        .build().unwrap();
+    let queued = handle.spawn_after(Duration::from_secs(5), || ());
+    let panicked = handle.spawn(|| panic!("shutdown"));
+    assert!(panicked.wait().unwrap_err().is_panic());
+    assert!(matches!(queued.wait(), Err(Error::PoolShutdown)));
+
+    // The worker waiting for the panicked task gets its result before the pool stops.
+    let handle = WorkerPoolBuilder::new()
+        .threads(2)
+        .panic_policy(PanicPolicy::Shutdown)
+        .build().unwrap();
+    let inner = handle.clone();
+    let waiting = handle.spawn(move || inner.spawn(|| panic!("shutdown")).wait());
+    let res = waiting.wait_timeout(Duration::from_secs(5)).ok().unwrap().unwrap();
+    assert!(res.unwrap_err().is_panic());
+    let start = std::time::Instant::now();
+    while !handle.is_shutdown() {
+        assert!(start.elapsed() < Duration::from_secs(5), "The pool didn't shut down");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn worker_restart() {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let restarts = Arc::new(AtomicUsize::new(0));
+    let cloned = Arc::clone(&restarts);
+    let panicked = AtomicBool::new(false);
+    let handle = WorkerPoolBuilder::new()
+        .threads(1)
+        .after_task(move || {
+            if !panicked.swap(true, Ordering::SeqCst) {
+                panic!("hook");
+            }
+        })
+        .on_worker_restart(move |panic| {
+            assert_eq!(panic.message(), Some("hook"));
+            cloned.fetch_add(1, Ordering::SeqCst);
+        })
+        .build().unwrap();
+
+    assert_eq!(handle.spawn(|| 1).wait().unwrap(), 1);
+    // The task runs on the thread that replaced the dead one.
+    assert_eq!(handle.spawn(|| 2).wait().unwrap(), 2);
+    assert_eq!(restarts.load(Ordering::SeqCst), 1);
+
+    let metrics = handle.metrics();
+    assert_eq!(metrics.restarts, 1);
+    assert_eq!(metrics.workers.len(), 1);
+    assert_eq!(handle.threads(), 1);
+
+    // Threads that can't even start are replaced with an increasing delay.
+    let handle = WorkerPoolBuilder::new()
+        .threads(1)
+        .on_start(|| panic!("start"))
+        .build().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(handle.metrics().restarts < 10);
+    handle.shutdown();
+
+    // Threads retiring left the count already, so they aren't replaced if the stop hook panics.
+    let handle = WorkerPoolBuilder::new()
+        .min_threads(1)
+        .max_threads(3)
+        .keep_alive(std::time::Duration::from_millis(20))
+        .on_stop(|| panic!("stop"))
+        .build().unwrap();
+    for _ in 0..2 {
+        let joins = (0..3)
+            .map(|_| handle.spawn(|| std::thread::sleep(std::time::Duration::from_millis(50))))
+            .collect::<Vec<_>>();
+        assert!(handle.threads() <= 3);
+        joins.into_iter().for_each(|join| join.wait().unwrap());
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert_eq!(handle.threads(), 1);
+        assert_eq!(handle.metrics().workers.len(), 1);
+    }
+    assert_eq!(handle.metrics().restarts, 0);
+}
+
+#[test]
+fn result_slot() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    // A pool whose only worker dies before running the first task.
+    fn pool() -> Handle {
+        let panicked = AtomicBool::new(false);
+        WorkerPoolBuilder::new()
+            .threads(1)
+            .before_task(move || {
+                if !panicked.swap(true, Ordering::SeqCst) {
+                    panic!("hook");
+                }
+            })
+            .build().unwrap()
+    }
+
+    let handle = pool();
+
+    // The task is dropped without running when the hook kills the worker.
+    let dropped = handle.spawn(|| 1);
+    assert!(matches!(dropped.wait(), Err(Error::Aborted)));
+
+    let running = handle.spawn(|| std::thread::sleep(Duration::from_millis(20)));
+    // Dropping the handles while their tasks are queued or running is fine.
+    drop(handle.spawn(|| vec![1, 2, 3]));
+    drop(handle.spawn(|| std::thread::sleep(Duration::from_millis(10))));
+    running.wait().unwrap();
+    assert_eq!(handle.spawn(|| 2).wait().unwrap(), 2);
+
+    // Tasks of a set get the error as well, instead of staying pending.
+    let handle = pool();
+    let mut set = handle.join_set();
+    set.spawn(|| 1);
+    assert!(matches!(set.next_completed(), Some((0, Err(Error::Aborted)))));
+    set.spawn(|| 2);
+    assert!(matches!(set.next_completed(), Some((1, Ok(2)))));
+
+    // So do futures, whose wakers are kept alive by their handles.
+    let handle = pool();
+    let dropped = handle.spawn_future(async { 1 });
+    let res = dropped.wait_timeout(Duration::from_secs(5)).ok().unwrap();
+    assert!(matches!(res, Err(Error::Aborted)));
+    assert_eq!(handle.spawn_future(async { 2 }).wait().unwrap(), 2);
+}
+
+#[test]
+fn try_spawn_errors() {
+    use crate::error::SpawnError;
+    use crate::handle::State;
+
+    assert!(matches!(crate::try_spawn(|| 1), Err(SpawnError::NoContext(_))));
+    assert!(matches!(crate::try_spawn_detached(|| ()), Err(SpawnError::NoContext(_))));
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(1).build().unwrap();
+    assert_eq!(handle.state(), State::Running);
+    assert!(!handle.is_shutdown());
+
+    // Building the pool enters its context.
+    assert_eq!(crate::try_spawn(|| 1).unwrap().wait().unwrap(), 1);
+    crate::try_spawn_detached(|| ()).unwrap();
+
+    let cloned = handle.clone();
+    cloned.shutdown();
+    assert_eq!(handle.state(), State::Stopped);
+    assert!(handle.is_shutdown());
+
+    let Err(error) = handle.try_spawn(|| 2) else {
+        panic!("The pool should be shut down");
+    };
+    assert_eq!(error.to_string(), "the pool was shut down");
+    assert_eq!(error.into_inner()(), 2);
+}
+
+#[test]
+fn no_lost_wakeups() {
+    use std::time::Duration;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(4).build().unwrap();
+
+    // Each task is pushed while the workers are going to sleep, a lost wakeup would leave it
+    // queued forever, as there are no timers waking the workers up.
+    for i in 0..5000 {
+        let Ok(result) = handle.spawn(move || i).wait_timeout(Duration::from_secs(5)) else {
+            panic!("The task was never executed");
+        };
+        assert_eq!(result.unwrap(), i);
+    }
+
+    let handles = (0..1000).map(|i| handle.spawn(move || i)).collect::<Vec<_>>();
+    for (i, join) in handles.into_iter().enumerate() {
+        assert_eq!(join.wait_timeout(Duration::from_secs(5)).ok().unwrap().unwrap(), i);
+    }
+}
+
+#[test]
+fn spawn_batch() {
+    use crate::builder::Backpressure;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(4).build().unwrap();
+
+    let handles = handle.spawn_batch((0..1000).map(|i| move || i * 2));
+    let results = handles.into_iter().map(|join| join.wait().unwrap()).collect::<Vec<_>>();
+    assert_eq!(results, (0..1000).map(|i| i * 2).collect::<Vec<_>>());
+    assert_eq!(handle.metrics().spawned, 1000);
+
+    // The batch is split to wait for room at the queue.
+    let handle = WorkerPoolBuilder::new()
+        .threads(2)
+        .queue_capacity(8)
+        .backpressure(Backpressure::Block)
+        .build().unwrap();
+
+    let count = Arc::new(AtomicUsize::new(0));
+    handle.spawn_detached_batch((0..100).map(|_| {
+        let count = Arc::clone(&count);
+        move || {
+            count.fetch_add(1, Ordering::SeqCst);
+        }
+    }));
+    handle.shutdown_graceful();
+    assert_eq!(count.load(Ordering::SeqCst), 100);
+}
+
+#[test]
+fn timers_without_threads() {
+    use std::time::Duration;
+
+    let handle = WorkerPoolBuilder::new()
+        .min_threads(0)
+        .max_threads(2)
+        .keep_alive(Duration::from_millis(10))
+        .build().unwrap();
+
+    let join = handle.spawn_after(Duration::from_millis(20), || 1);
+    assert_eq!(join.wait_timeout(Duration::from_secs(5)).ok().unwrap().unwrap(), 1);
+
+    // Let the idle threads retire.
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(handle.threads(), 0);
+
+    let periodic = handle.spawn_periodic(|| (), Duration::from_millis(30), Some(3));
+    let start = std::time::Instant::now();
+    while !periodic.is_finished() {
+        assert!(start.elapsed() < Duration::from_secs(5), "The periodic task stopped running");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn shutdown_graceful_from_worker() {
+    let handle = WorkerPoolBuilder::new()
+        .threads(1).build().unwrap();
+
+    let inner = handle.clone();
+    let join = handle.spawn(move || {
+        let queued = inner.spawn(|| 7);
+        inner.clone().shutdown_graceful();
+        queued.wait()
+    });
+    assert_eq!(join.wait().unwrap().unwrap(), 7);
+}
+
+#[test]
+fn backpressure_from_worker() {
+    use std::time::Duration;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(1)
+        .queue_capacity(1)
+        .build().unwrap();
+
+    let inner = handle.clone();
+    let join = handle.spawn(move || {
+        let first = inner.spawn(|| 1);
+        // The queue is full, so the worker executes the first task to make room.
+        let second = inner.spawn(|| 2);
+        (first, second)
+    });
+    let (first, second) = join.wait_timeout(Duration::from_secs(5)).ok().unwrap().unwrap();
+    assert!(first.is_finished());
+    assert_eq!(first.wait().unwrap() + second.wait().unwrap(), 3);
+}
+
+#[test]
+fn shutdown_pending_future() {
+    use std::time::Duration;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(1).build().unwrap();
+
+    let pending = handle.spawn_future(std::future::pending::<()>());
+    std::thread::sleep(Duration::from_millis(50));
+    handle.clone().shutdown();
+    let res = pending.wait_timeout(Duration::from_secs(5)).ok().unwrap();
+    assert!(matches!(res, Err(Error::PoolShutdown)));
+    assert_eq!(handle.metrics().aborted, 1);
+}
+
+#[test]
+fn parallel_without_room() {
+    use std::time::Duration;
+    use crate::builder::Backpressure;
+    use crate::join_set::Order;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(2)
+        .queue_capacity(1)
+        .backpressure(Backpressure::Fail)
+        .build().unwrap();
+
+    let doubled = handle.par_map(0..64u64, |value| value * 2);
+    assert_eq!(doubled, (0..64).map(|value| value * 2).collect::<Vec<_>>());
+    // Parts the caller ran itself hold their slots until a worker pops them.
+    std::thread::sleep(Duration::from_millis(50));
+
+    let inner = handle.clone();
+    let join = handle.spawn(move || {
+        let mut set = inner.join_set();
+        for i in 0..4u64 {
+            set.spawn(move || i);
+        }
+        set.collect(Order::Submission).into_iter().map(|res| res.unwrap()).sum::<u64>()
+    });
+    assert_eq!(join.wait_timeout(Duration::from_secs(5)).ok().unwrap().unwrap(), 6);
+
+    // Nothing can be queued anymore, so everything runs in the current thread.
+    handle.clone().shutdown();
+    assert_eq!(handle.join(|| 1, || 2), (1, 2));
+    assert_eq!(handle.scope(|s| s.spawn(|| 3).join().unwrap()), 3);
+}
+
+#[test]
+fn set_and_scope_metrics() {
+    use std::time::Duration;
+
+    let handle = WorkerPoolBuilder::new()
+        .threads(2).build().unwrap();
+
+    let mut set = handle.join_set();
+    set.spawn(|| panic!("set"));
+    set.spawn(|| ());
+    assert_eq!(set.collect(crate::join_set::Order::Submission).len(), 2);
+
+    let joined = handle.scope(|s| {
+        s.spawn(|| ());
+        s.spawn(|| panic!("scope")).join()
+    });
+    assert!(matches!(joined, Err(Error::Panicked(_))));
+
+    // The workers count the outcome once the tasks return.
+    std::thread::sleep(Duration::from_millis(50));
+    let metrics = handle.metrics();
+    assert_eq!(metrics.panicked, 2);
+    assert_eq!(metrics.completed, 2);
+}
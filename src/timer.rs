@@ -1,25 +1,144 @@
-use crossbeam_queue::SegQueue;
-use crate::driver::Either;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+use std::time::Instant;
+use crate::core::Core;
+use crate::driver::{Driver, Either};
+use crate::error::Error;
 use crate::periodic::PeriodicTask;
+use crate::priority::Priority;
 use crate::sync::Task;
-use drain_filter_polyfill::VecExt;
-use parking_lot::Condvar;
 
-/// The queue of periodic task, the task here are scheduled at the main queue when needed.
+/// A periodic or delayed task waiting for its deadline.
+struct Entry {
+    at: Instant,
+    id: u64,
+    task: Either<Task, PeriodicTask>
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    // Reversed, so the heap pops the earliest deadline first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.at.cmp(&self.at).then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+/// The key of a delayed task at the timer, used to remove it when it gets aborted.
+pub struct TimerKey {
+    pub core: Arc<Core>,
+    pub id: u64
+}
+
+impl TimerKey {
+    pub fn remove(&self) {
+        if self.core.timer.lock().remove(self.id) {
+            self.core.counters.aborted(1);
+        }
+    }
+}
+
+/// The queue of periodic and delayed tasks ordered by deadline, the task here are scheduled at
+/// the main queue when their deadline is reached.
 #[derive(Default)]
 pub struct Timer {
-    pub waiting: Vec<PeriodicTask>,
+    heap: BinaryHeap<Entry>,
+    next_id: u64
 }
 
 impl Timer {
-    pub fn schedule(&mut self, task: PeriodicTask) {
-        self.waiting.push(task);
+    /// Pushes a task, returning its id and whether it has the earliest deadline of the timer.
+    fn push(&mut self, at: Instant, task: Either<Task, PeriodicTask>) -> (u64, bool) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let earliest = self.heap.peek().map(|entry| at < entry.at).unwrap_or(true);
+        self.heap.push(Entry {
+            at,
+            id,
+            task
+        });
+        (id, earliest)
+    }
+
+    /// Schedules a periodic task, returning whether it has the earliest deadline of the timer.
+    pub fn schedule(&mut self, task: PeriodicTask) -> bool {
+        self.push(task.next(), Either::Right(task)).1
+    }
+
+    /// Schedules a task to be executed at the given instant, returning its id and whether it
+    /// has the earliest deadline of the timer.
+    pub fn schedule_delayed(&mut self, task: Task, at: Instant) -> (u64, bool) {
+        self.push(at, Either::Left(task))
+    }
+
+    /// Removes the delayed task with the given id, dropping it without running, returning
+    /// whether it was found.
+    pub fn remove(&mut self, id: u64) -> bool {
+        let len = self.heap.len();
+        self.heap.retain(|entry| entry.id != id);
+        self.heap.len() != len
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Moves the tasks whose deadline has been reached to the main queue, returning the
+    /// deadline of the next task and how many tasks were moved.
+    ///
+    /// The worker threads aren't woken up here, so they aren't while holding the timer.
+    pub fn schedule_available(&mut self, to: &Driver) -> (Option<Instant>, usize) {
+        let now = Instant::now();
+        let mut moved = 0;
+
+        while self.heap.peek().map(|entry| entry.at <= now).unwrap_or(false) {
+            let entry = self.heap.pop().unwrap();
+            match entry.task {
+                // Cancelled tasks are simply dropped.
+                Either::Right(task) if task.is_finished() => (),
+                Either::Right(mut task) if task.is_paused() => {
+                    if task.skip() {
+                        self.schedule(task);
+                    }
+                },
+                task => {
+                    to.schedule(task, Priority::Normal);
+                    moved += 1;
+                }
+            }
+        }
+
+        (self.heap.peek().map(|entry| entry.at), moved)
     }
 
-    pub fn schedule_available(&mut self, cv: &Condvar, to: &SegQueue<Either<Task, PeriodicTask>>) {
-        for task in self.waiting.drain_filter(|task| task.can_run()) {
-            to.push(Either::Right(task));
-            cv.notify_one();
+    /// Aborts all the delayed tasks and drops the periodic ones, returning how many delayed
+    /// tasks were aborted.
+    pub fn clear(&mut self) -> usize {
+        let mut aborted = 0;
+        for entry in self.heap.drain() {
+            if let Either::Left(task) = entry.task {
+                task.abort(Error::PoolShutdown);
+                aborted += 1;
+            }
         }
+        aborted
     }
 }
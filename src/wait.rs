@@ -1,6 +1,20 @@
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
 use std::task::{Waker, Context};
+use std::time::Instant;
 use crossbeam_utils::sync::{Parker, Unparker};
-use crate::error::Result;
+use parking_lot::Mutex;
+use crate::error::{Error, Result};
+
+/// The result hasn't been set yet.
+const EMPTY: u8 = 0;
+/// The result is being written by the sender that won the race to set it.
+const WRITING: u8 = 1;
+/// The result is ready to be taken.
+const READY: u8 = 2;
+/// The result has been taken by the waiter.
+const TAKEN: u8 = 3;
 
 pub enum Notifier {
     Unparker(Unparker),
@@ -16,49 +30,125 @@ impl Notifier {
     }
 }
 
-pub struct Inner<T> {
-    pub data: Option<Result<T>>,
-    pub notifier: Option<Notifier>
+/// A oneshot slot used to send the result of a task to its handle.
+///
+/// The result is set at most once, the first set wins and the rest are dropped. The slot is
+/// freed once both the senders and the waiter are dropped, so either side can go away at any
+/// time.
+struct Inner<T> {
+    state: AtomicU8,
+    /// Only written by the sender that moves the state from `EMPTY` to `WRITING`, and only read
+    /// by the waiter once the state is `READY`.
+    data: UnsafeCell<Option<Result<T>>>,
+    notifier: Mutex<Option<Notifier>>,
+    /// The number of senders alive, the last one sets [`Aborted`] if no result was set.
+    ///
+    /// [`Aborted`]: crate::error::Error::Aborted
+    senders: AtomicUsize
 }
 
+// SAFETY: Access to `data` is synchronized through `state`, so the result is only moved between
+// threads, which is fine as long as it is `Send`.
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
 impl<T> Inner<T> {
-    pub fn new() -> *mut Self {
-        Box::into_raw(Box::new(Self {
-            data: None,
-            notifier: None
-        })) as *mut _
+    /// Sets the result if it wasn't set yet, returning whether it was set.
+    fn set(&self, result: Result<T>) -> bool {
+        let won = self.state
+            .compare_exchange(EMPTY, WRITING, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok();
+        if !won {
+            return false;
+        }
+
+        // SAFETY: We moved the state out of `EMPTY`, so no one else writes the result, and the
+        // waiter doesn't read it until the state is `READY`.
+        unsafe { *self.data.get() = Some(result); }
+        self.state.store(READY, Ordering::Release);
+
+        // Taken after publishing the result, so a notifier registered after this sees it ready.
+        if let Some(notifier) = self.notifier.lock().take() {
+            notifier.notify();
+        }
+        true
+    }
+
+    fn is_ready(&self) -> bool {
+        self.state.load(Ordering::Acquire) == READY
     }
 }
 
-pub struct Waiter<T> {
-    inner: *mut Inner<T>
+/// Creates a new slot, returning the sender used by the task and the waiter used by its handle.
+pub fn channel<T>() -> (Sender<T>, Waiter<T>) {
+    let inner = Arc::new(Inner {
+        state: AtomicU8::new(EMPTY),
+        data: UnsafeCell::new(None),
+        notifier: Mutex::new(None),
+        senders: AtomicUsize::new(1)
+    });
+
+    (Sender { inner: Arc::clone(&inner) }, Waiter { inner })
 }
 
-impl<T> Waiter<T> {
-    pub fn new(inner: *mut Inner<T>) -> Self {
-        Self {
-            inner
-        }
+/// The side of the slot that sets the result, it can be cloned so both the task and its abort
+/// function can hold one.
+///
+/// If every sender is dropped without setting the result, the waiter gets [`Aborted`].
+///
+/// [`Aborted`]: crate::error::Error::Aborted
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>
+}
+
+impl<T> Sender<T> {
+    /// Sets the result, doing nothing if it was already set.
+    pub fn set(&self, result: Result<T>) {
+        self.inner.set(result);
     }
+}
 
-    pub fn is_valid(&self) -> bool {
-        !self.inner.is_null()
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.inner.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            inner: Arc::clone(&self.inner)
+        }
     }
+}
 
-    pub fn inner(&mut self) -> Option<&mut Inner<T>> {
-        if self.is_valid() {
-            Some(unsafe { &mut *self.inner })
-        } else {
-            None
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.inner.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.inner.set(Err(Error::Aborted));
         }
     }
+}
+
+/// The side of the slot that waits for the result, owned by the handle of the task.
+pub struct Waiter<T> {
+    inner: Arc<Inner<T>>
+}
 
+impl<T> Waiter<T> {
+    /// Takes the result if it is ready.
     pub fn try_get(&mut self) -> Option<Result<T>> {
-        if !self.is_valid() {
-            panic!("Result dropped");
-        } else {
-            self.inner().map(|inner| inner.data.take())?
+        if !self.inner.is_ready() {
+            return None;
         }
+
+        self.inner.state.store(TAKEN, Ordering::Relaxed);
+        // SAFETY: The state is `READY`, so the result has been written and no sender will touch
+        // it anymore, and we are the only waiter.
+        unsafe { (*self.inner.data.get()).take() }
+    }
+
+    /// Replaces the notifier, returning the result instead if it was set in the meantime.
+    fn register(&mut self, notifier: Notifier) -> Option<Result<T>> {
+        *self.inner.notifier.lock() = Some(notifier);
+        // The sender may have set the result before we registered the notifier, and it only
+        // notifies the ones registered before taking the lock.
+        self.try_get()
     }
 
     pub fn wait(&mut self) -> Result<T> {
@@ -67,25 +157,68 @@ impl<T> Waiter<T> {
         }
 
         let parker = Parker::new();
-        self.inner().map(|inner| {
-            inner.notifier = Some(Notifier::Unparker(parker.unparker().clone()));
-        });
-        parker.park();
+        if let Some(item) = self.register(Notifier::Unparker(parker.unparker().clone())) {
+            return item;
+        }
 
-        self.try_get().unwrap()
+        loop {
+            parker.park();
+            if let Some(item) = self.try_get() {
+                return item;
+            }
+        }
     }
 
-    pub fn set_waker(&mut self, cx: &Context) {
-        self.inner().map(|inner| {
-            inner.notifier = Some(Notifier::Waker(cx.waker().clone()))
-        });
+    /// Waits for the result until the deadline, returning [`None`] if it is reached first.
+    ///
+    /// [`None`]: std::option::Option::None
+    pub fn wait_deadline(&mut self, deadline: Instant) -> Option<Result<T>> {
+        if let Some(item) = self.try_get() {
+            return Some(item);
+        }
+
+        let parker = Parker::new();
+        if let Some(item) = self.register(Notifier::Unparker(parker.unparker().clone())) {
+            return Some(item);
+        }
+
+        loop {
+            if Instant::now() >= deadline {
+                // The parker is going away, so don't leave it registered.
+                self.inner.notifier.lock().take();
+                return self.try_get();
+            }
+            parker.park_deadline(deadline);
+            if let Some(item) = self.try_get() {
+                return Some(item);
+            }
+        }
     }
-}
 
-impl<T> Drop for Waiter<T> {
-    fn drop(&mut self) {
-        if !self.inner.is_null() {
-            unsafe { let _ = Box::from_raw(self.inner); }
+    /// Returns whether the result is available.
+    pub fn is_ready(&self) -> bool {
+        self.inner.is_ready()
+    }
+
+    /// Sets the result directly, used when the task has been aborted before running.
+    pub fn set(&self, result: Result<T>) {
+        self.inner.set(result);
+    }
+
+    /// Registers the waker of the current task, replacing the previous one, returning the
+    /// result instead if it was set in the meantime.
+    pub fn poll(&mut self, cx: &Context) -> Option<Result<T>> {
+        if let Some(item) = self.try_get() {
+            return Some(item);
+        }
+
+        {
+            let mut notifier = self.inner.notifier.lock();
+            match notifier.as_ref() {
+                Some(Notifier::Waker(waker)) if waker.will_wake(cx.waker()) => (),
+                _ => *notifier = Some(Notifier::Waker(cx.waker().clone()))
+            }
         }
+        self.try_get()
     }
 }
@@ -1,7 +1,15 @@
+use std::cell::Cell;
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+use crossbeam_utils::sync::Unparker;
 use crate::core::Core;
+use crate::driver::Item;
+use crate::error::Panic;
 use crate::handle::Handle;
+use crate::metrics::WorkerCounters;
+use crate::sync::Outcome;
 
 pub struct Worker {
     core: Arc<Core>
@@ -16,37 +24,216 @@ impl Worker {
 
     pub fn run(self) {
         crate::context::set(Handle { core: Arc::clone(&self.core) });
+        self.core.driver.register();
+        let counters = self.core.counters.add_worker();
 
-        self.core.hooks.on_start.as_ref().map(|fun| fun.call());
+        // Task panics are caught by the tasks themselves, so this only catches the panics of the
+        // hooks and the pool itself.
+        let retired = Cell::new(false);
+        let result = catch_unwind(AssertUnwindSafe(|| self.work(&counters, &retired)));
 
-        while !self.core.exit {
-            let timeout = self.try_schedule_periodical();
-            if self.core.driver.queue.is_empty() {
-                let mut lock = self.core.mutex.lock();
+        self.core.driver.unregister();
+        self.core.counters.remove_worker(&counters);
+        match result {
+            // A task may have been pushed to the timer while we were retiring. The thread left
+            // the count already, so it isn't replaced even if the stop hook panicked.
+            _ if retired.get() => self.core.ensure_timer_thread(),
+            Ok(()) => {
+                self.core.threads.fetch_sub(1, Ordering::AcqRel);
+            },
+            Err(payload) => {
+                let panic = Panic::new(payload, None);
+                self.core.restart_worker(panic, counters.tasks() == 0);
+            }
+        }
+
+        // Let the remaining threads pick up the tasks left in our local queues, unless the pool
+        // stopped and nobody would.
+        if !self.core.is_running() {
+            self.core.abort_left();
+        } else if !self.core.driver.is_empty() {
+            self.core.sleepers.notify_one();
+        }
+        // Notify the threads waiting for the pool to shut down.
+        {
+            let _lock = self.core.mutex.lock();
+            self.core.condvar.notify_all();
+        }
+        crate::context::clear();
+    }
+
+    /// Executes tasks until the pool stops or the thread retires, setting `retired` as soon as
+    /// the thread leaves the count.
+    fn work(&self, counters: &WorkerCounters, retired: &Cell<bool>) {
+        if let Some(fun) = self.core.hooks.on_start.as_ref() {
+            fun.call();
+        }
+
+        let unparker = crate::park::unparker();
+        let mut idle_since = None;
+
+        loop {
+            if !self.core.is_running() {
+                break;
+            }
 
-                if timeout {
-                    self.core.condvar.wait_for(&mut lock, Duration::from_millis(150));
-                } else {
-                    self.core.condvar.wait(&mut lock);
+            let idle = idle_since.map(|since: Instant| since.elapsed()).unwrap_or_default();
+            if self.core.try_retire(idle) {
+                retired.set(true);
+                break;
+            }
+
+            let (deadline, epoch) = schedule_timers(&self.core);
+            if self.core.driver.is_empty() {
+                // Start counting before parking, not once the first park times out.
+                let since = *idle_since.get_or_insert_with(Instant::now);
+
+                // Registered before checking for the last time, so anything that happens after
+                // the check wakes us up.
+                self.core.sleepers.register(&unparker);
+
+                // Only one thread sleeps until the next deadline, the rest sleep until notified.
+                let watching = deadline.is_some() && self.core.sleepers.watch(&unparker);
+
+                if self.core.is_draining() || !self.core.is_running() {
+                    self.unregister(&unparker, watching);
+                    break;
+                }
+
+                // A task was pushed, the pool was resized or the earliest deadline changed
+                // since we checked.
+                if !self.core.driver.is_empty()
+                    || self.core.threads.load(Ordering::SeqCst)
+                        > self.core.max_threads.load(Ordering::SeqCst)
+                    || self.core.timer_epoch.load(Ordering::SeqCst) != epoch
+                {
+                    self.unregister(&unparker, watching);
+                    continue;
+                }
+
+                let deadline = deadline.filter(|_| watching);
+                let can_retire = self.core.threads.load(Ordering::Acquire)
+                    > self.core.min_threads.load(Ordering::Acquire);
+                let retire_at = can_retire.then(|| since + self.core.config.keep_alive);
+
+                let parked = Instant::now();
+                let timed_out = crate::park::park(deadline.into_iter().chain(retire_at).min());
+                counters.park(parked.elapsed());
+
+                // We were woken up to do something else, so let another thread watch.
+                if self.unregister(&unparker, watching) && !timed_out {
+                    self.core.sleepers.notify_one();
                 }
             }
-            if let Some(task) = self.core.driver.queue.pop() {
-                self.core.hooks.before_task.as_ref().map(|fun| fun.call());
-                task.run();
-                self.core.hooks.after_task.as_ref().map(|fun| fun.call());
+            if let Some(task) = self.core.pop() {
+                idle_since = None;
+                let started = Instant::now();
+                run_task(&self.core, task);
+                counters.task(started.elapsed());
+            } else {
+                idle_since.get_or_insert_with(Instant::now);
             }
         }
 
-        self.core.hooks.on_stop.as_ref().map(|fun| fun.call());
-        crate::context::clear();
+        if let Some(fun) = self.core.hooks.on_stop.as_ref() {
+            fun.call();
+        }
+    }
+
+    /// Removes the thread from the sleeping ones, returning whether it was still watching the
+    /// timer.
+    fn unregister(&self, unparker: &Arc<Unparker>, watching: bool) -> bool {
+        self.core.sleepers.unregister(unparker);
+        watching && self.core.sleepers.unwatch(unparker)
+    }
+}
+
+/// Runs a task taken from the queue, calling the task hooks and counting how it ended.
+fn run_task(core: &Core, task: Item) {
+    if let Some(fun) = core.hooks.before_task.as_ref() {
+        fun.call();
+    }
+    match task.run() {
+        Some(Outcome::Completed) => core.counters.completed(),
+        Some(Outcome::Panicked) => core.counters.panicked(),
+        Some(Outcome::Aborted) => core.counters.aborted(1),
+        Some(Outcome::Pending) | None => ()
+    }
+    if let Some(fun) = core.hooks.after_task.as_ref() {
+        fun.call();
+    }
+}
+
+/// Executes queued tasks until the queue is empty or the deadline is reached, if the current
+/// thread is a worker of the pool, used to drain the queue from a worker shutting down the pool.
+pub fn drain(core: &Core, deadline: Option<Instant>) {
+    if !core.driver.is_worker() {
+        return;
+    }
+
+    while deadline.map(|deadline| Instant::now() < deadline).unwrap_or(true) {
+        match core.pop() {
+            Some(task) => run_task(core, task),
+            None => break
+        }
     }
+}
+
+/// Executes the queued tasks from a worker thread until `done` returns `true`, sleeping while
+/// there is nothing to do.
+///
+/// Whatever makes `done` return `true` must then unpark the thread through the unparker given
+/// by [`park::unparker`], so the wakeup can't be lost.
+///
+/// [`park::unparker`]: crate::park::unparker
+pub fn help<F>(core: &Core, done: F)
+where
+    F: Fn() -> bool
+{
+    let unparker = crate::park::unparker();
+    // Whether we were woken up through the list, taking the wakeup of a task we didn't pop.
+    let mut woken = false;
+
+    while !done() {
+        if let Some(task) = core.pop() {
+            woken = false;
+            run_task(core, task);
+            continue;
+        }
 
-    fn try_schedule_periodical(&self) -> bool {
-        if let Some(mut lock) = self.core.timer.try_lock() {
-            lock.schedule_available(&self.core.condvar, &self.core.driver.queue);
-            true
-        } else {
-            false
+        let (deadline, epoch) = schedule_timers(core);
+        core.sleepers.register(&unparker);
+        if done()
+            || !core.driver.is_empty()
+            || core.timer_epoch.load(Ordering::SeqCst) != epoch
+        {
+            woken |= !core.sleepers.unregister(&unparker);
+            continue;
         }
+
+        crate::park::park(deadline);
+        woken |= !core.sleepers.unregister(&unparker);
+    }
+
+    // Pass the wakeup on, so the task doesn't wait for us to finish.
+    if woken && !core.driver.is_empty() {
+        core.sleepers.notify_one();
     }
 }
+
+/// Moves the tasks of the timer whose deadline has been reached to the queue, returning the
+/// next deadline and the epoch of the timer when it was checked.
+///
+/// The current thread executes one of the tasks moved, so the rest of them wake up other
+/// threads once the timer is unlocked.
+fn schedule_timers(core: &Core) -> (Option<Instant>, usize) {
+    let (deadline, epoch, moved) = {
+        let mut lock = core.timer.lock();
+        let epoch = core.timer_epoch.load(Ordering::SeqCst);
+        let (deadline, moved) = lock.schedule_available(&core.driver);
+        (deadline, epoch, moved)
+    };
+
+    let _ = core.sleepers.notify(moved.saturating_sub(1));
+    (deadline, epoch)
+}